@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use rsdict::RsDict;
+use rsdict::{InterleavedRsDict, RsDict};
 use succinct::bit_vec::{BitVecPush, BitVector};
 use succinct::rank::{JacobsonRank, Rank9, RankSupport};
 use succinct::select::{BinSearchSelect, Select0Support, Select1Support};
@@ -52,6 +52,28 @@ fn bench_rank(c: &mut Criterion) {
         },
         |r, i| r.rank(i, true)
     );
+    bench_one_rank(
+        c,
+        "rsdict::interleaved::rank",
+        |bits| {
+            let mut rs_dict = RsDict::with_capacity(NUM_BITS);
+            for b in bits.iter() {
+                rs_dict.push(b);
+            }
+            InterleavedRsDict::from(rs_dict)
+        },
+        |r, i| r.rank(i, true)
+    );
+    {
+        let mut r = RsDict::with_capacity(NUM_BITS);
+        for b in random_bits(NUM_BITS).iter() {
+            r.push(b);
+        }
+        let indices: Vec<u64> = random_indices(1000, NUM_BITS).into_iter().map(|i| i as u64).collect();
+        c.bench_function("rsdict::rank_many", |b| {
+            b.iter(|| r.rank_many(black_box(&indices), true))
+        });
+    }
     bench_one_rank(
         c,
         "jacobson::rank",
@@ -107,6 +129,19 @@ fn bench_select(c: &mut Criterion) {
         |r, i| r.select0(i),
         |r, i| r.select1(i),
     );
+    bench_one_select(
+        c,
+        "rsdict::interleaved",
+        |bits| {
+            let mut rs_dict = RsDict::with_capacity(NUM_BITS);
+            for b in bits.iter() {
+                rs_dict.push(b);
+            }
+            InterleavedRsDict::from(rs_dict)
+        },
+        |r, i| r.select0(i),
+        |r, i| r.select1(i),
+    );
     bench_one_select(
         c,
         "rank9::binsearch",
@@ -135,6 +170,33 @@ pub fn build_random_sorted_vector(size: usize, max: u64) -> Vec<u64> {
     vector
 }
 
+// Uniformly random bits are almost all stored raw, so sparse bitmaps are what
+// exercise decoding the enumerative codes in rank and select.
+fn bench_sparse(c: &mut Criterion) {
+    for &density in &[8, 32] {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let r: RsDict = (0..NUM_BITS)
+            .map(|_| rng.gen_bool(1.0 / density as f64))
+            .collect();
+        let positions = random_indices(1000, NUM_BITS);
+        let ranks = random_indices(1000, r.count_ones());
+        c.bench_function(&format!("rsdict::sparse_1_{}::rank", density), |b| {
+            b.iter(|| {
+                for &ix in &positions {
+                    r.rank(black_box(ix as u64), true);
+                }
+            })
+        });
+        c.bench_function(&format!("rsdict::sparse_1_{}::select1", density), |b| {
+            b.iter(|| {
+                for &ix in &ranks {
+                    r.select1(black_box(ix as u64));
+                }
+            })
+        });
+    }
+}
+
 fn bench_iter(c: &mut Criterion) {
 
     // crete a random vector of values
@@ -163,5 +225,5 @@ fn bench_iter(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_iter); //, bench_rank, bench_select);
+criterion_group!(benches, bench_rank, bench_select, bench_sparse, bench_iter);
 criterion_main!(benches);
@@ -4,7 +4,9 @@ use std::fs;
 use std::path::Path;
 
 // We want to precompute a table for binomial coefficients ahead of
-// time, since computing them on the fly is expensive.  First, we
+// time, since computing them on the fly is expensive.  The table only depends
+// on the small block size (64 bits), so it's shared by every choice of
+// `RsDict`'s large block and select sampling sizes.  First, we
 // can build the table using the recurrence:
 //
 // B(n, n) = 1
@@ -75,20 +77,19 @@ fn row_len(n: usize) -> usize {
     n / 2 + 1
 }
 
-fn lookup(row: &[u64], n: usize, k: usize) -> u64 {
+fn lookup(row: &[u128], n: usize, k: usize) -> u128 {
     row[cmp::min(k, n - k)]
 }
 
-fn main() {
-    let out_dir = env::var_os("OUT_DIR").expect("Failed to get output directory");
-    let dst_path = Path::new(&out_dir).join("binomial.rs");
-
+// Build the table for all `n <= max_n`.  Every coefficient with `n <= 128`
+// fits in a `u128`, since the largest is B(128, 64) < 2^127.
+fn binomial_table(max_n: usize) -> Vec<u128> {
     let mut table = vec![];
 
     // Base case for n = 0;
-    table.push(1u64);
+    table.push(1u128);
 
-    for n in 1..65usize {
+    for n in 1..=max_n {
         // Base case for k = 0
         table.push(1);
         for k in 1..row_len(n) {
@@ -98,8 +99,137 @@ fn main() {
             table.push(val);
         }
     }
+    table
+}
+
+// Number of bits to represent a 128-bit block with `k` bits set, i.e.
+// ceil(log2(B(128, k))).  As with the hand-written `ENUM_CODE_LENGTH` table for
+// 64-bit blocks, codes longer than `MAX_WIDE_CODE_LENGTH` bits (about 72% of
+// the block) are stored raw as the full 128 bits.
+const MAX_WIDE_CODE_LENGTH: u32 = 92;
+
+fn wide_code_lengths(table: &[u128]) -> Vec<u8> {
+    (0..=128)
+        .map(|k| {
+            let coefficient = lookup(&table[row_start(128)..], 128, k);
+            let bits = 128 - (coefficient - 1).leading_zeros();
+            if bits > MAX_WIDE_CODE_LENGTH {
+                128
+            } else {
+                bits as u8
+            }
+        })
+        .collect()
+}
+
+// Classes up to this many ones (or down to this many zeros) get variable
+// length codes in `enum_code::ENUM_CODE_LENGTH`, and the rest are stored raw.
+// The decoder only handles sparse codes directly, since the code of a dense
+// block's complement is `B(64, k) - 1` minus its own: complementing every bit
+// reverses the lexicographic order that codes count in.
+const MAX_SPARSE_CLASS: usize = 14;
+
+// The decoder in `enum_code` walks a 64-bit block a byte at a time.  Say
+// we're at the start of byte `c`, so there are `n = 64 - 8c` bits remaining
+// with `k` of them set.  Codes are ordered lexicographically with the first
+// bit most significant, so the byte is all zeros exactly when the remaining
+// code is below `B(n - 8, k)` (the number of ways to place `k` ones in the
+// bits after it), which lets sparse blocks skip most bytes with a single
+// comparison.
+fn chunk_zeros(table: &[u128]) -> Vec<[u64; MAX_SPARSE_CLASS + 1]> {
+    (0..8)
+        .map(|c| {
+            let n = 64 - 8 * c;
+            let mut row = [0u64; MAX_SPARSE_CLASS + 1];
+            for (k, offset) in row.iter_mut().enumerate() {
+                *offset = binomial(table, n - 8, k) as u64;
+            }
+            row
+        })
+        .collect()
+}
+
+// Other bytes are decoded four bits at a time.  At the start of nibble `c`,
+// with `n = 64 - 4c` bits remaining and `k` of them set, each possible value
+// `v` of the nibble owns a contiguous range of codes, starting at the sum of
+// `B(n - j - 1, k_j)` over its set bits `j`, where `k_j` is the number of ones
+// left at bit `j`.  We store these starts in lexicographic order, i.e. indexed
+// by `v` with its bits reversed, so the nibble's value is given by how many
+// starts are at most the remaining code.  Values that can't occur (with more
+// than `k` ones, or too few to leave at most `n - 4` after them) take the
+// start of the next possible value, or `u64::MAX` if there isn't one, so
+// they're counted exactly when that value is.
+fn nibble_starts(table: &[u128]) -> Vec<[[u64; 16]; MAX_SPARSE_CLASS + 1]> {
+    (0..16)
+        .map(|c| {
+            let n = 64 - 4 * c;
+            let mut rows = [[0u64; 16]; MAX_SPARSE_CLASS + 1];
+            for (k, row) in rows.iter_mut().enumerate() {
+                let mut next_start = u64::MAX;
+                for i in (0..16).rev() {
+                    let v = reverse_nibble(i);
+                    let ones = v.count_ones() as usize;
+                    if ones <= k && k - ones <= n - 4 {
+                        let mut start = 0;
+                        let mut k_j = k;
+                        for j in 0..4 {
+                            if (v >> j) & 1 != 0 {
+                                start += binomial(table, n - j - 1, k_j);
+                                k_j -= 1;
+                            }
+                        }
+                        next_start = start as u64;
+                    }
+                    row[i] = next_start;
+                }
+            }
+            rows
+        })
+        .collect()
+}
+
+fn reverse_nibble(i: usize) -> usize {
+    (0..4).fold(0, |v, j| v | ((i >> j) & 1) << (3 - j))
+}
+
+// `B(n, k)`, or zero if `k > n`.
+fn binomial(table: &[u128], n: usize, k: usize) -> u128 {
+    if k > n {
+        0
+    } else {
+        lookup(&table[row_start(n)..], n, k)
+    }
+}
+
+fn main() {
+    let out_dir = env::var_os("OUT_DIR").expect("Failed to get output directory");
+    let dst_path = Path::new(&out_dir).join("binomial.rs");
+
+    let table = binomial_table(128);
+    let narrow_table: Vec<u64> = table[..row_start(65)].iter().map(|&v| v as u64).collect();
+    let code_lengths = wide_code_lengths(&table);
+    let chunk_zeros = chunk_zeros(&table);
+    let nibble_starts = nibble_starts(&table);
 
-    let code = format!("pub const COEFFICIENT_TABLE: &[u64; {}] = &{:?};", table.len(), table);
+    let code = format!(
+        "pub const COEFFICIENT_TABLE: &[u64; {}] = &{:?};\n\
+         pub const COEFFICIENT_TABLE_128: &[u128; {}] = &{:?};\n\
+         pub const ENUM_CODE_LENGTH_128: &[u8; {}] = &{:?};\n\
+         pub const MAX_SPARSE_CLASS: u8 = {};\n\
+         pub const CHUNK_ZEROS: &[[u64; {}]; 8] = &{:?};\n\
+         pub const NIBBLE_STARTS: &[[[u64; 16]; {}]; 16] = &{:?};\n",
+        narrow_table.len(),
+        narrow_table,
+        table.len(),
+        table,
+        code_lengths.len(),
+        code_lengths,
+        MAX_SPARSE_CLASS,
+        MAX_SPARSE_CLASS + 1,
+        chunk_zeros,
+        MAX_SPARSE_CLASS + 1,
+        nibble_starts,
+    );
 
     fs::write(&dst_path, code).expect("Failed to write binomial coefficient table");
     println!("cargo:rerun-if-changed=build.rs");
@@ -0,0 +1,87 @@
+/*
+ * C bindings for rsdict, built with the `capi` cargo feature:
+ *
+ *     cargo rustc --release --features capi --crate-type staticlib
+ *
+ * Keep in sync with `src/capi.rs`, which `tests/capi.rs` checks.
+ */
+#ifndef RSDICT_H
+#define RSDICT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle to a rank/select dictionary with the default block sizes. */
+typedef struct rsdict rsdict_t;
+
+/* Create an empty dictionary.  Free it with `rsdict_free`. */
+rsdict_t *rsdict_new(void);
+
+/*
+ * Create a dictionary from the first `len` bits of `num_words` 64-bit words,
+ * where bit `i` is bit `i % 64` of word `i / 64`.  Returns NULL if
+ * `len > 64 * num_words` or `len` doesn't fit in a `size_t`.  `words` may be
+ * NULL if `num_words` is zero.
+ */
+rsdict_t *rsdict_from_words(const uint64_t *words, size_t num_words, uint64_t len);
+
+/* Free a dictionary.  `r` may be NULL. */
+void rsdict_free(rsdict_t *r);
+
+/* Append a bit to the end of the dictionary. */
+void rsdict_push(rsdict_t *r, bool bit);
+
+/* Return the number of bits in the dictionary. */
+uint64_t rsdict_len(const rsdict_t *r);
+
+/* Return the number of bits set in the dictionary. */
+uint64_t rsdict_count_ones(const rsdict_t *r);
+
+/* Return the `pos`th bit, or false if `pos` is out of bounds. */
+bool rsdict_get_bit(const rsdict_t *r, uint64_t pos);
+
+/*
+ * Count the `bit` values before `pos`.  Positions past the end count every
+ * `bit` value in the dictionary.
+ */
+uint64_t rsdict_rank(const rsdict_t *r, uint64_t pos, bool bit);
+
+/*
+ * Find the position of the `rank`th (zero-indexed) `bit` value and store it
+ * in `*pos`, returning whether there is one.  `*pos` is left unchanged
+ * otherwise.
+ */
+bool rsdict_select(const rsdict_t *r, uint64_t rank, bool bit, uint64_t *pos);
+
+/*
+ * Return a 64-bit fingerprint of the bitmap's contents that's the same across
+ * platforms and processes.  See `RsDict::fingerprint` for the algorithm.
+ */
+uint64_t rsdict_fingerprint(const rsdict_t *r);
+
+/* Return the length of the dictionary's serialized form. */
+size_t rsdict_serialized_len(const rsdict_t *r);
+
+/*
+ * Serialize the dictionary into `buf` if it fits in `buf_len` bytes, and
+ * return the serialized length either way.  The format is the same as
+ * `RsDict::to_bytes` in Rust.  `buf` may be NULL if `buf_len` is zero.
+ */
+size_t rsdict_serialize(const rsdict_t *r, uint8_t *buf, size_t buf_len);
+
+/*
+ * Deserialize a dictionary written by `rsdict_serialize`, returning NULL if
+ * the bytes aren't a valid dictionary.
+ */
+rsdict_t *rsdict_deserialize(const uint8_t *buf, size_t buf_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RSDICT_H */
@@ -0,0 +1,212 @@
+use alloc::vec::Vec;
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, SMALL_BLOCK_SIZE};
+use super::enum_code::ENUM_CODE_LENGTH;
+use super::iter::RsDictIterator;
+use super::plain::PlainIterOnes;
+use super::{pack_bits, PlainRsDict, RsDict};
+use core::iter::FromIterator;
+
+// Only compress if the estimated size is at most 90% of the plain bitmap's,
+// since decoding isn't free.
+const MAX_COMPRESSED_RATIO: (u64, u64) = (9, 10);
+
+/// Rank and select dictionary that picks between [`RsDict`] and [`PlainRsDict`] based on how
+/// compressible its input is.
+///
+/// [`AutoRsDict::from_blocks`] computes the size of both representations' rank structures
+/// for the input and only picks [`RsDict`] if it's sufficiently smaller.  For [`RsDict`], that's each small block's code
+/// length as stored (so 64 bits for the classes it stores raw), a byte of class per small block
+/// and a rank and pointer per large block.  For [`PlainRsDict`], it's the raw words plus a rank
+/// per large block.  Both sample select positions the same way, so the select indices are left
+/// out.  Random bitmaps with about half their bits set stay plain and sparse or dense ones get
+/// compressed.
+///
+/// ```
+/// use rsdict::AutoRsDict;
+///
+/// let sparse = AutoRsDict::from_blocks((0..64).map(|i| 1 << (i % 64)));
+/// assert!(sparse.is_compressed());
+///
+/// let random = AutoRsDict::from_blocks((0..64u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15)));
+/// assert!(!random.is_compressed());
+/// assert_eq!(random.len(), 64 * 64);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum AutoRsDict {
+    /// Small blocks stored with their enumerative codes.
+    Compressed(RsDict),
+    /// Small blocks stored as raw words.
+    Plain(PlainRsDict),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $r:ident => $e:expr) => {
+        match $self {
+            AutoRsDict::Compressed($r) => $e,
+            AutoRsDict::Plain($r) => $e,
+        }
+    };
+}
+
+impl FromIterator<bool> for AutoRsDict {
+    fn from_iter<I: IntoIterator<Item = bool>>(bits: I) -> Self {
+        let (blocks, tail) = pack_bits(bits.into_iter());
+        let mut r = Self::from_blocks(blocks.into_iter());
+        for bit in tail {
+            r.push(bit);
+        }
+        r
+    }
+}
+
+impl AutoRsDict {
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit blocks, choosing the
+    /// representation from the blocks' compressed size.
+    ///
+    /// The blocks are buffered in a `Vec` so they can be measured before building the chosen
+    /// dictionary, which temporarily takes another 8 bytes per block.
+    pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
+        let blocks: Vec<u64> = blocks.collect();
+        let num_lblocks = (blocks.len() as u64 * SMALL_BLOCK_SIZE + DEFAULT_LARGE_BLOCK_SIZE - 1)
+            / DEFAULT_LARGE_BLOCK_SIZE;
+        // `RsDict` stores a rank and pointer per large block, and
+        // `PlainRsDict` just a rank.
+        let compressed_bits = blocks
+            .iter()
+            .map(|b| ENUM_CODE_LENGTH[b.count_ones() as usize] as u64 + 8)
+            .sum::<u64>()
+            + num_lblocks * 128;
+        let plain_bits = blocks.len() as u64 * SMALL_BLOCK_SIZE + num_lblocks * 64;
+
+        let (num, den) = MAX_COMPRESSED_RATIO;
+        if compressed_bits * den <= plain_bits * num {
+            AutoRsDict::Compressed(RsDict::from_blocks(blocks.into_iter()))
+        } else {
+            AutoRsDict::Plain(PlainRsDict::from_blocks(blocks.into_iter()))
+        }
+    }
+
+    /// Return whether the bitmap is stored in compressed form.
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, AutoRsDict::Compressed(..))
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    #[inline]
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        dispatch!(self, r => r.rank(pos, bit))
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    #[inline]
+    pub fn get_bit(&self, pos: u64) -> bool {
+        dispatch!(self, r => r.get_bit(pos))
+    }
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    #[inline]
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        dispatch!(self, r => r.select(rank, bit))
+    }
+
+    /// Specialized version of [`AutoRsDict::select`] for finding positions of zeros.
+    #[inline]
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        dispatch!(self, r => r.select0(rank))
+    }
+
+    /// Specialized version of [`AutoRsDict::select`] for finding positions of ones.
+    #[inline]
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        dispatch!(self, r => r.select1(rank))
+    }
+
+    /// Return the length of the underlying bitmap.
+    #[inline]
+    pub fn len(&self) -> usize {
+        dispatch!(self, r => r.len())
+    }
+
+    /// Return whether the underlying bitmap is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        dispatch!(self, r => r.is_empty())
+    }
+
+    /// Count the number of set bits in the underlying bitmap.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        dispatch!(self, r => r.count_ones())
+    }
+
+    /// Count the number of unset bits in the underlying bitmap.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        dispatch!(self, r => r.count_zeros())
+    }
+
+    /// Return an iterator over the positions of the ones in the underlying bitmap.
+    #[inline]
+    pub fn iter(&self) -> AutoIterOnes<'_> {
+        match self {
+            AutoRsDict::Compressed(r) => AutoIterOnes::Compressed(r.iter()),
+            AutoRsDict::Plain(r) => AutoIterOnes::Plain(r.iter()),
+        }
+    }
+
+    /// Push a bit at the end of the underlying bitmap.  This doesn't change the representation.
+    #[inline]
+    pub fn push(&mut self, bit: bool) {
+        dispatch!(self, r => r.push(bit))
+    }
+}
+
+/// Iterator over the positions of the ones in an `AutoRsDict`.
+#[derive(Debug)]
+pub enum AutoIterOnes<'a> {
+    /// Iterator over an [`AutoRsDict::Compressed`] dictionary.
+    Compressed(RsDictIterator<'a>),
+    /// Iterator over an [`AutoRsDict::Plain`] dictionary.
+    Plain(PlainIterOnes<'a>),
+}
+
+impl<'a> Iterator for AutoIterOnes<'a> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            AutoIterOnes::Compressed(it) => it.next(),
+            AutoIterOnes::Plain(it) => it.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::AutoRsDict;
+    use crate::test_helpers::test_block;
+    use crate::RsDict;
+
+    #[quickcheck]
+    fn qc_auto(blocks: Vec<u64>, sparse: bool) {
+        let blocks: Vec<u64> = blocks.into_iter().map(|b| test_block(b, sparse)).collect();
+        let r = RsDict::from_blocks(blocks.iter().cloned());
+        let auto = AutoRsDict::from_blocks(blocks.into_iter());
+        assert_eq!(auto.len(), r.len());
+        for i in 0..r.len() as u64 {
+            assert_eq!(auto.get_bit(i), r.get_bit(i));
+            assert_eq!(auto.rank(i, true), r.rank(i, true));
+        }
+        for i in 0..=r.count_ones() as u64 {
+            assert_eq!(auto.select1(i), r.select1(i));
+        }
+        for i in 0..=r.count_zeros() as u64 {
+            assert_eq!(auto.select0(i), r.select0(i));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE};
+use super::RsDict;
+
+/// Builder for an `RsDict` that only stores the select indices it needs.
+///
+/// By default, `RsDict` samples every 4096th one and zero to accelerate `select1` and `select0`.
+/// Turning off an index saves its memory and construction time, and the corresponding select
+/// falls back to a binary search over the large blocks, so the full query API keeps working.
+///
+/// ```
+/// use rsdict::RsDictBuilder;
+///
+/// // A rank-only dictionary.
+/// let r = RsDictBuilder::new()
+///     .select0_index(false)
+///     .select1_index(false)
+///     .from_blocks(vec![0b1011, 0b110].into_iter());
+/// assert_eq!(r.rank(65, true), 3);
+/// assert_eq!(r.select1(3), Some(65));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RsDictBuilder<
+    const LARGE_BLOCK_SIZE: u64 = { DEFAULT_LARGE_BLOCK_SIZE },
+    const SELECT_BLOCK_SIZE: u64 = { DEFAULT_SELECT_BLOCK_SIZE },
+> {
+    capacity: usize,
+    select1_index: bool,
+    select0_index: bool,
+}
+
+impl RsDictBuilder {
+    /// Create a builder with the default block sizes and both select indices.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_block_sizes()
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> Default
+    for RsDictBuilder<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    #[inline]
+    fn default() -> Self {
+        Self::with_block_sizes()
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDictBuilder<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Same as [`RsDictBuilder::new`] but with custom block sizes.
+    #[inline]
+    pub fn with_block_sizes() -> Self {
+        Self {
+            capacity: 0,
+            select1_index: true,
+            select0_index: true,
+        }
+    }
+
+    /// Preallocate space for `n` bits in dictionaries created with [`RsDictBuilder::build`].
+    #[inline]
+    pub fn capacity(mut self, n: usize) -> Self {
+        self.capacity = n;
+        self
+    }
+
+    /// Whether to build the index for `select1`.
+    #[inline]
+    pub fn select1_index(mut self, enabled: bool) -> Self {
+        self.select1_index = enabled;
+        self
+    }
+
+    /// Whether to build the index for `select0`.
+    #[inline]
+    pub fn select0_index(mut self, enabled: bool) -> Self {
+        self.select0_index = enabled;
+        self
+    }
+
+    /// Create an empty dictionary to push bits onto.
+    #[inline]
+    pub fn build(self) -> RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        RsDict::with_capacity_and_indices(self.capacity, self.select1_index, self.select0_index)
+    }
+
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit blocks.
+    #[inline]
+    pub fn from_blocks(
+        self,
+        blocks: impl Iterator<Item = u64>,
+    ) -> RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        RsDict::from_blocks_with_indices(blocks, self.select1_index, self.select0_index)
+    }
+}
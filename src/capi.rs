@@ -0,0 +1,194 @@
+//! C bindings, enabled with the `capi` feature and declared in
+//! `include/rsdict.h`.
+//!
+//! Build the crate as a static or dynamic library to link against it, e.g.
+//! `cargo rustc --release --features capi --crate-type staticlib`.  Handles
+//! are opaque `rsdict_t` pointers to an [`RsDict`] with the default block
+//! sizes.  Out-of-bounds queries return a value rather than panicking, since
+//! a panic can't unwind into C.
+use super::{from_padded_blocks, RsDict};
+use core::convert::TryFrom;
+use core::{ptr, slice};
+use std::boxed::Box;
+
+/// Opaque handle to a dictionary.
+#[allow(non_camel_case_types)]
+pub type rsdict_t = RsDict;
+
+/// Create an empty dictionary.  Free it with [`rsdict_free`].
+#[no_mangle]
+pub extern "C" fn rsdict_new() -> *mut rsdict_t {
+    Box::into_raw(Box::new(RsDict::new()))
+}
+
+/// Create a dictionary from the first `len` bits of `num_words` 64-bit words, where bit `i` is
+/// bit `i % 64` of word `i / 64`.  Returns `NULL` if `len > 64 * num_words` or `len` doesn't
+/// fit in a `size_t`.
+///
+/// # Safety
+/// `words` must point to `num_words` readable words, or may be `NULL` if `num_words` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_from_words(
+    words: *const u64,
+    num_words: usize,
+    len: u64,
+) -> *mut rsdict_t {
+    // `64 * num_words` only overflows if it's more than any `len`.
+    if matches!((num_words as u64).checked_mul(64), Some(bits) if len > bits) {
+        return ptr::null_mut();
+    }
+    let len = match usize::try_from(len) {
+        Ok(len) => len,
+        Err(_) => return ptr::null_mut(),
+    };
+    let words = if num_words == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(words, num_words)
+    };
+    let r = from_padded_blocks(words.iter().copied(), len);
+    Box::into_raw(Box::new(r))
+}
+
+/// Free a dictionary.  `r` may be `NULL`.
+///
+/// # Safety
+/// `r` must be `NULL` or a handle returned by this library that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_free(r: *mut rsdict_t) {
+    if !r.is_null() {
+        drop(Box::from_raw(r));
+    }
+}
+
+/// Append a bit to the end of the dictionary.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_push(r: *mut rsdict_t, bit: bool) {
+    (*r).push(bit);
+}
+
+/// Return the number of bits in the dictionary.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_len(r: *const rsdict_t) -> u64 {
+    (*r).len() as u64
+}
+
+/// Return the number of bits set in the dictionary.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_count_ones(r: *const rsdict_t) -> u64 {
+    (*r).count_ones() as u64
+}
+
+/// Return the `pos`th bit, or `false` if `pos` is out of bounds.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_get_bit(r: *const rsdict_t, pos: u64) -> bool {
+    let r = &*r;
+    pos < r.len() as u64 && r.get_bit(pos)
+}
+
+/// Count the `bit` values before `pos`.  Positions past the end count every `bit` value in the
+/// dictionary.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_rank(r: *const rsdict_t, pos: u64, bit: bool) -> u64 {
+    let r = &*r;
+    if pos < r.len() as u64 {
+        r.rank(pos, bit)
+    } else if bit {
+        r.count_ones() as u64
+    } else {
+        r.count_zeros() as u64
+    }
+}
+
+/// Find the position of the `rank`th (zero-indexed) `bit` value and store it in `*pos`,
+/// returning whether there is one.  `*pos` is left unchanged otherwise.
+///
+/// # Safety
+/// `r` must be a live handle and `pos` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_select(
+    r: *const rsdict_t,
+    rank: u64,
+    bit: bool,
+    pos: *mut u64,
+) -> bool {
+    match (*r).select(rank, bit) {
+        Some(p) => {
+            *pos = p;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Return the dictionary's [`RsDict::fingerprint`].
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_fingerprint(r: *const rsdict_t) -> u64 {
+    (*r).fingerprint()
+}
+
+/// Return the length of the dictionary's serialized form.
+///
+/// # Safety
+/// `r` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_serialized_len(r: *const rsdict_t) -> usize {
+    (*r).serialized_len()
+}
+
+/// Serialize the dictionary into `buf` in the format of [`RsDict::to_bytes`] if it fits in
+/// `buf_len` bytes, and return the serialized length either way.
+///
+/// # Safety
+/// `r` must be a live handle and `buf` must point to `buf_len` writable bytes, or may be `NULL`
+/// if `buf_len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_serialize(
+    r: *const rsdict_t,
+    buf: *mut u8,
+    buf_len: usize,
+) -> usize {
+    let r = &*r;
+    let len = r.serialized_len();
+    if len <= buf_len {
+        let bytes = r.to_bytes();
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buf, len);
+    }
+    len
+}
+
+/// Deserialize a dictionary written by [`rsdict_serialize`] or [`RsDict::to_bytes`], returning
+/// `NULL` if the bytes aren't a valid dictionary.
+///
+/// # Safety
+/// `buf` must point to `buf_len` readable bytes, or may be `NULL` if `buf_len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn rsdict_deserialize(buf: *const u8, buf_len: usize) -> *mut rsdict_t {
+    let bytes = if buf_len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(buf, buf_len)
+    };
+    match RsDict::from_bytes(bytes) {
+        Ok(r) => Box::into_raw(Box::new(r)),
+        Err(_) => ptr::null_mut(),
+    }
+}
@@ -1,4 +1,3 @@
 pub const SMALL_BLOCK_SIZE: u64 = 64;
-pub const LARGE_BLOCK_SIZE: u64 = 1024;
-pub const SELECT_BLOCK_SIZE: u64 = 4096;
-pub const SMALL_BLOCK_PER_LARGE_BLOCK: u64 = LARGE_BLOCK_SIZE / SMALL_BLOCK_SIZE;
+pub const DEFAULT_LARGE_BLOCK_SIZE: u64 = 1024;
+pub const DEFAULT_SELECT_BLOCK_SIZE: u64 = 4096;
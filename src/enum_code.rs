@@ -1,5 +1,6 @@
-use std::cmp;
+use core::cmp;
 use super::constants::SMALL_BLOCK_SIZE;
+use super::rank_acceleration;
 
 mod binomial {
     // Load in the generated binomial coefficient table generated by `build.rs`.
@@ -43,77 +44,131 @@ pub fn encode(value: u64, class: u8) -> (u8, u64) {
     (code_len, code)
 }
 
+// Value of the nibble at each lexicographic index, i.e. with its bits reversed.
+const REVERSE_NIBBLE: [u8; 16] = [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15];
+
+// Decode the next four bits of a sparse block starting at bit `4 * nibble`,
+// given the remaining `code` and number of ones `k` after the previous
+// nibbles.  See `build.rs` for the table of each nibble value's first code,
+// which we binary search for the last one at most `code`.  The first entry is
+// always zero, so the search can start there.
+#[inline(always)]
+fn decode_nibble(code: &mut u64, k: &mut u8, nibble: usize) -> u8 {
+    use self::binomial::NIBBLE_STARTS;
+    let starts = &NIBBLE_STARTS[nibble][*k as usize];
+    let mut i = 0;
+    for &step in &[8, 4, 2, 1] {
+        if starts[i + step] <= *code {
+            i += step;
+        }
+    }
+    *code -= starts[i];
+    let value = REVERSE_NIBBLE[i];
+    *k -= value.count_ones() as u8;
+    value
+}
+
+// Decode the next eight bits of a sparse block starting at bit `8 * chunk`,
+// skipping straight past the chunk if it's all zeros.
+#[inline(always)]
+fn decode_chunk(code: &mut u64, k: &mut u8, chunk: usize) -> u8 {
+    use self::binomial::CHUNK_ZEROS;
+    if *code < CHUNK_ZEROS[chunk][*k as usize] {
+        return 0;
+    }
+    let low = decode_nibble(code, k, 2 * chunk);
+    low | decode_nibble(code, k, 2 * chunk + 1) << 4
+}
+
+// Convert a variable length code to the code of a sparse block, returning it
+// along with its class and whether it's the complement of the original block.
+// The decoding tables only cover sparse blocks, so a dense block is decoded
+// from its complement, whose code counts down from the end instead.
+#[inline(always)]
+fn to_sparse(code: u64, class: u8) -> (u64, u8, bool) {
+    use self::binomial::MAX_SPARSE_CLASS;
+    debug_assert!(!is_raw(class));
+    if class <= MAX_SPARSE_CLASS {
+        return (code, class, false);
+    }
+    let complement = SMALL_BLOCK_SIZE as u8 - class;
+    let num_codes = binomial_coefficient(SMALL_BLOCK_SIZE as u8, complement);
+    (num_codes - 1 - code, complement, true)
+}
+
+#[inline(always)]
+fn is_raw(class: u8) -> bool {
+    ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8
+}
+
 #[inline]
-pub fn decode(mut code: u64, class: u8) -> u64 {
-    if ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8 {
+pub fn decode(code: u64, class: u8) -> u64 {
+    if is_raw(class) {
         return code;
     }
+    let (mut code, mut k, complement) = to_sparse(code, class);
     let mut value = 0u64;
-    let mut k = class;
-    for i in 0..(SMALL_BLOCK_SIZE as u8) {
-        let n = SMALL_BLOCK_SIZE as u8 - i;
-        if n > k {
-            let zero_case_num = binomial_coefficient(n - 1, k);
-            if code >= zero_case_num {
-                value |= 1 << i;
-                code -= zero_case_num;
-                k -= 1;
-            }
-        }
-        // If `n == k`, this bit must be set.
-        else {
-            value |= 1 << i;
-            k -= 1;
+    for chunk in 0..8 {
+        if k == 0 {
+            break;
         }
+        value |= (decode_chunk(&mut code, &mut k, chunk) as u64) << (chunk * 8);
+    }
+    if complement {
+        !value
+    } else {
+        value
     }
-    value
 }
 
+// Whether `code` encodes some block with `class` bits set.
 #[inline]
-pub fn decode_bit(mut code: u64, class: u8, pos: u64) -> bool {
-    if ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8 {
+pub fn is_valid(code: u64, class: u8) -> bool {
+    if class as u64 > SMALL_BLOCK_SIZE {
+        return false;
+    }
+    if is_raw(class) {
+        return code.count_ones() as u8 == class;
+    }
+    code < binomial_coefficient(SMALL_BLOCK_SIZE as u8, class)
+}
+
+#[inline]
+pub fn decode_bit(code: u64, class: u8, pos: u64) -> bool {
+    if is_raw(class) {
         return (code >> pos) & 1 != 0;
     }
-    let mut k = class;
-    for i in 0..(pos as u8) {
-        let n = SMALL_BLOCK_SIZE as u8 - i;
-        let zero_case_num = 
-            if n > k {
-                binomial_coefficient(n as u8 - 1, k as u8)
-            } else {
-                0
-            };
-        if code >= zero_case_num {
-            code -= zero_case_num;
-            k -= 1;
-        }
+    let (mut code, mut k, complement) = to_sparse(code, class);
+    let mut byte = 0;
+    for chunk in 0..=(pos / 8) as usize {
+        byte = decode_chunk(&mut code, &mut k, chunk);
     }
-    let n = SMALL_BLOCK_SIZE  - pos;
-    code >= 
-        if n > k as u64 {
-            binomial_coefficient(n as u8 - 1, k as u8)
-        } else {
-            0
-        }
+    ((byte >> (pos % 8)) & 1 != 0) != complement
 }
 
 #[inline(always)]
-fn rank_impl(mut code: u64, class: u8, pos: u64) -> u64 {
-    if ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8 {
+fn rank_impl(code: u64, class: u8, pos: u64) -> u64 {
+    if is_raw(class) {
         return (code & ((1 << pos) - 1)).count_ones() as u64;
     }
-    let mut cur_rank = class;
-    for i in 0..pos {
-        let n = SMALL_BLOCK_SIZE - i - 1;
-        let zero_case_num = binomial_coefficient(n as u8, cur_rank);
-        if code >= zero_case_num {
-            code -= zero_case_num;
-            cur_rank -= 1;
-        }
+    let (mut code, mut k, complement) = to_sparse(code, class);
+    let sparse_class = k;
+    for chunk in 0..(pos / 8) as usize {
+        decode_chunk(&mut code, &mut k, chunk);
+    }
+    let mut rank = (sparse_class - k) as u64;
+    if pos % 8 != 0 {
+        let byte = decode_chunk(&mut code, &mut k, (pos / 8) as usize);
+        rank += (byte & ((1 << (pos % 8)) - 1)).count_ones() as u64;
+    }
+    if complement {
+        pos - rank
+    } else {
+        rank
     }
-    (class - cur_rank) as u64
 }
 
+#[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "popcnt")]
 #[inline]
 unsafe fn rank_with_popcount(code: u64, class: u8, pos: u64) -> u64 {
@@ -122,87 +177,586 @@ unsafe fn rank_with_popcount(code: u64, class: u8, pos: u64) -> u64 {
 
 #[inline]
 pub fn rank(code: u64, class: u8, pos: u64) -> u64 {
-    if is_x86_feature_detected!("popcnt") {
-        unsafe { rank_with_popcount(code, class, pos) }
-    } else {
-        rank_impl(code, class, pos)
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_x86_feature!("popcnt") {
+            return unsafe { rank_with_popcount(code, class, pos) };
+        }
     }
+    rank_impl(code, class, pos)
 }
 
+// Position of the `rank`th (zero-indexed) set bit in `code`.
 #[inline]
-// Adapted from https://lemire.me/blog/2018/02/21/iterating-over-set-bits-quickly/
-pub fn select1_raw(mut code: u64, mut rank: u64) -> u64 {
+pub fn select1_raw(code: u64, rank: u64) -> u64 {
     debug_assert!(rank < code.count_ones() as u64);
-    for _ in 0..64 {
-        let t = code & code.wrapping_neg();
-        if rank == 0 {
-            return code.trailing_zeros() as u64;
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_x86_feature!("bmi2") {
+            return unsafe { select1_raw_bmi2(code, rank) };
         }
-        rank -= 1;
-        code ^= t;
     }
-    debug_assert!(false, "select1_raw past end of codeword");
-    0
+    select1_raw_broadword(code, rank)
 }
 
+// `pdep` deposits a single bit at the position of the `rank`th set bit of
+// `code`, which `tzcnt` then reads off.  Note that `pdep` is microcoded and
+// slow on AMD CPUs before Zen 3, but still no worse than the fallback.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi1,bmi2")]
 #[inline]
-pub fn select1(mut code: u64, class: u8, mut rank: u64) -> u64 {
-    if ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8 {
-        return select1_raw(code, rank);
+unsafe fn select1_raw_bmi2(code: u64, rank: u64) -> u64 {
+    use core::arch::x86_64::{_pdep_u64, _tzcnt_u64};
+    _tzcnt_u64(_pdep_u64(1 << rank, code))
+}
+
+// Broadword select, following Vigna, "Broadword Implementation of Rank/Select
+// Queries": find the byte containing our bit with a bytewise prefix sum of the
+// popcounts, and then select within that byte.
+#[inline]
+fn select1_raw_broadword(code: u64, rank: u64) -> u64 {
+    const L8: u64 = 0x0101_0101_0101_0101;
+    const H8: u64 = 0x8080_8080_8080_8080;
+
+    let mut counts = code - ((code >> 1) & 0x5555_5555_5555_5555);
+    counts = (counts & 0x3333_3333_3333_3333) + ((counts >> 2) & 0x3333_3333_3333_3333);
+    counts = (counts + (counts >> 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+
+    // Byte `i` of `prefix` is the number of bits set in bytes `0..=i`, which is
+    // at most 64, so setting each byte's high bit keeps the subtraction below
+    // from borrowing across bytes.  Then, the high bit survives exactly in the
+    // bytes whose prefix exceeds `rank`.
+    let prefix = counts.wrapping_mul(L8);
+    let exceeds = ((prefix | H8) - (rank + 1) * L8) & H8;
+    let byte_offset = exceeds.trailing_zeros() as u64 & !7;
+
+    let prefix_before = ((prefix << 8) >> byte_offset) & 0xFF;
+    let mut byte = (code >> byte_offset) & 0xFF;
+    for _ in 0..(rank - prefix_before) {
+        byte &= byte - 1;
     }
-    let mut k = class;
-    for i in 0..SMALL_BLOCK_SIZE {
-        let n = SMALL_BLOCK_SIZE - i;
-        let zero_case_num = 
-            if n > k as u64 {
-                binomial_coefficient(n as u8 - 1, k as u8)
-            } else {
-                0
-            };
+    byte_offset + byte.trailing_zeros() as u64
+}
 
-        if code >= zero_case_num {
-            if rank == 0 {
-                return i;
-            }
-            rank -= 1;
-            code -= zero_case_num;
-            k -= 1;
+// Position of the `rank`th one (or zero if `!ones`) in a sparse block.
+#[inline(always)]
+fn select_sparse(mut code: u64, mut k: u8, mut rank: u64, ones: bool) -> u64 {
+    for chunk in 0..8 {
+        let mut byte = decode_chunk(&mut code, &mut k, chunk);
+        if !ones {
+            byte = !byte;
         }
+        let count = byte.count_ones() as u64;
+        if rank < count {
+            return chunk as u64 * 8 + select1_raw(byte as u64, rank);
+        }
+        rank -= count;
     }
-    debug_assert!(false, "select1 past end of codeword");
+    debug_assert!(false, "select past end of codeword");
     0
 }
 
 #[inline]
-pub fn select0(mut code: u64, class: u8, mut rank: u64) -> u64 {
-    if ENUM_CODE_LENGTH[class as usize] == SMALL_BLOCK_SIZE as u8 {
+pub fn select1(code: u64, class: u8, rank: u64) -> u64 {
+    if is_raw(class) {
+        return select1_raw(code, rank);
+    }
+    let (code, k, complement) = to_sparse(code, class);
+    select_sparse(code, k, rank, !complement)
+}
+
+#[inline]
+pub fn select0(code: u64, class: u8, rank: u64) -> u64 {
+    if is_raw(class) {
         return select1_raw(!code, rank);
     }
-    let mut k = class as usize;
-    for i in 0..SMALL_BLOCK_SIZE {
-        let n = SMALL_BLOCK_SIZE - i - 1;
-        let zero_case_num = binomial_coefficient(n as u8, k as u8);
-        if code >= zero_case_num {
-            code -= zero_case_num;
-            k -= 1;
+    let (code, k, complement) = to_sparse(code, class);
+    select_sparse(code, k, rank, complement)
+}
+
+// Precomputed number of bits it takes to represent a block of 64 bits where k
+// of them are set: ceil(log(binomial_coefficient(64, k))) for k in [0, 64].
+// However, note that once the code length is sufficiently long (> 46), we just
+// set it to 64 to hit the fast paths above.  It's not worth using a variable
+// length code to save a few bits when it makes computing `rank` and `select`
+// much more expensive.
+pub const ENUM_CODE_LENGTH: &[u8; 65] = &[
+    0, 6, 11, 16, 20, 23, 27, 30, 33, 35, 38, 40, 42, 44, 46, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+    64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
+    64, 64, 46, 44, 42, 40, 38, 35, 33, 30, 27, 23, 20, 16, 11, 6, 0,
+];
+
+/// Enumerative codes for 128-bit small blocks, stored in a `u128`.  These
+/// mirror the 64-bit routines above, using the wider tables from `build.rs`.
+pub mod wide {
+    use super::binomial::{COEFFICIENT_TABLE_128, ENUM_CODE_LENGTH_128};
+    use core::cmp;
+
+    pub const SMALL_BLOCK_SIZE: u64 = 128;
+
+    // Number of bits it takes to represent a 128-bit block where k of them are
+    // set.  As with `super::ENUM_CODE_LENGTH`, long codes are stored raw.
+    pub const ENUM_CODE_LENGTH: &[u8; 129] = ENUM_CODE_LENGTH_128;
+
+    #[inline]
+    fn binomial_coefficient(n: u8, k: u8) -> u128 {
+        debug_assert!(n <= 128 && k <= n);
+
+        // See `build.rs` for an explanation of how the table is indexed.
+        let (q, r) = (n as usize / 2, n as usize % 2);
+        let row_start = (q + r) * (q + 1);
+        let k = cmp::min(k, n - k) as usize;
+        COEFFICIENT_TABLE_128[row_start + k]
+    }
+
+    // Number of codes for the remaining `n` bits with `k` set when the
+    // current bit is zero.  If all of the remaining bits are set, the current
+    // bit must be set too.
+    #[inline]
+    fn zero_case_num(n: u64, k: u8) -> u128 {
+        if n >= k as u64 {
+            binomial_coefficient(n as u8, k)
         } else {
-            if rank == 0 {
-                return i;
+            0
+        }
+    }
+
+    #[inline]
+    fn is_raw(class: u8) -> bool {
+        ENUM_CODE_LENGTH[class as usize] as u64 == SMALL_BLOCK_SIZE
+    }
+
+    #[inline]
+    pub fn encode(value: u128, class: u8) -> (u8, u128) {
+        debug_assert_eq!(value.count_ones() as u8, class);
+        let code_len = ENUM_CODE_LENGTH[class as usize];
+        if is_raw(class) {
+            return (code_len, value);
+        }
+        let mut code = 0u128;
+        let mut k = class;
+        for i in 0..SMALL_BLOCK_SIZE {
+            if (value >> i) & 1 != 0 {
+                code += zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+                k -= 1;
             }
-            rank -= 1;
         }
+        (code_len, code)
+    }
+
+    // Decode the whole block in one pass, stopping once all of its ones are
+    // placed, rather than calling `decode_bit` for each position.
+    #[inline]
+    pub fn decode(mut code: u128, class: u8) -> u128 {
+        if is_raw(class) {
+            return code;
+        }
+        let mut value = 0u128;
+        let mut k = class;
+        let mut i = 0;
+        while k > 0 {
+            let zero_case_num = zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+            if code >= zero_case_num {
+                value |= 1 << i;
+                code -= zero_case_num;
+                k -= 1;
+            }
+            i += 1;
+        }
+        value
+    }
+
+    // Whether `code` encodes some block with `class` bits set.
+    #[inline]
+    pub fn is_valid(code: u128, class: u8) -> bool {
+        if class as u64 > SMALL_BLOCK_SIZE {
+            return false;
+        }
+        if is_raw(class) {
+            return code.count_ones() as u8 == class;
+        }
+        code < binomial_coefficient(SMALL_BLOCK_SIZE as u8, class)
+    }
+
+    #[inline]
+    pub fn decode_bit(mut code: u128, class: u8, pos: u64) -> bool {
+        if is_raw(class) {
+            return (code >> pos) & 1 != 0;
+        }
+        let mut k = class;
+        for i in 0..pos {
+            let zero_case_num = zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+            if code >= zero_case_num {
+                code -= zero_case_num;
+                k -= 1;
+            }
+        }
+        code >= zero_case_num(SMALL_BLOCK_SIZE - pos - 1, k)
+    }
+
+    #[inline]
+    pub fn rank(mut code: u128, class: u8, pos: u64) -> u64 {
+        if is_raw(class) {
+            return (code & ((1 << pos) - 1)).count_ones() as u64;
+        }
+        let mut k = class;
+        for i in 0..pos {
+            let zero_case_num = zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+            if code >= zero_case_num {
+                code -= zero_case_num;
+                k -= 1;
+            }
+        }
+        (class - k) as u64
+    }
+
+    #[inline]
+    pub fn select1_raw(code: u128, rank: u64) -> u64 {
+        let low = code as u64;
+        let low_ones = low.count_ones() as u64;
+        if rank < low_ones {
+            super::select1_raw(low, rank)
+        } else {
+            64 + super::select1_raw((code >> 64) as u64, rank - low_ones)
+        }
+    }
+
+    #[inline]
+    pub fn select1(mut code: u128, class: u8, mut rank: u64) -> u64 {
+        if is_raw(class) {
+            return select1_raw(code, rank);
+        }
+        let mut k = class;
+        for i in 0..SMALL_BLOCK_SIZE {
+            let zero_case_num = zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+            if code >= zero_case_num {
+                if rank == 0 {
+                    return i;
+                }
+                rank -= 1;
+                code -= zero_case_num;
+                k -= 1;
+            }
+        }
+        debug_assert!(false, "select1 past end of codeword");
+        0
+    }
+
+    #[inline]
+    pub fn select0(mut code: u128, class: u8, mut rank: u64) -> u64 {
+        if is_raw(class) {
+            return select1_raw(!code, rank);
+        }
+        let mut k = class;
+        for i in 0..SMALL_BLOCK_SIZE {
+            let zero_case_num = zero_case_num(SMALL_BLOCK_SIZE - i - 1, k);
+            if code >= zero_case_num {
+                code -= zero_case_num;
+                k -= 1;
+            } else {
+                if rank == 0 {
+                    return i;
+                }
+                rank -= 1;
+            }
+        }
+        debug_assert!(false, "select0 past end of codeword");
+        0
+    }
+}
+
+/// The bits of a small block, which `RsDict` stores with an enumerative code:
+/// `u64` by default, or `u128` for [`WideRsDict`](crate::WideRsDict).  This
+/// trait is sealed, so it can't be implemented outside of this crate.
+pub trait SmallBlock: sealed::Block {}
+
+impl SmallBlock for u64 {}
+impl SmallBlock for u128 {}
+
+mod sealed {
+    use core::fmt::Debug;
+    use core::hash::Hash;
+
+    // The enumerative code routines and bit twiddling `RsDict` needs for a
+    // small block size.  Codes are stored in `sb_indices` a 64-bit word at a
+    // time, low word first.
+    pub trait Block: Copy + Debug + Eq + Hash {
+        const BITS: u64;
+        const WORDS: usize = (Self::BITS / 64) as usize;
+        const ZERO: Self;
+
+        fn code_length(class: u8) -> u8;
+
+        // Sum the classes and code lengths of `classes[start..end]`, where
+        // `classes` must be padded like `rank_acceleration::scan_block` needs.
+        fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64);
+
+        fn encode(self, class: u8) -> Self;
+        fn decode(self, class: u8) -> Self;
+        fn is_valid(self, class: u8) -> bool;
+        fn decode_bit(self, class: u8, pos: u64) -> bool;
+        fn rank(self, class: u8, pos: u64) -> u64;
+        fn select0(self, class: u8, rank: u64) -> u64;
+        fn select1(self, class: u8, rank: u64) -> u64;
+
+        // Operations on the raw bits, for the last block and for iterating
+        // over decoded blocks.
+        fn ones(self) -> u64;
+        fn trailing_zeros(self) -> u64;
+        fn clear_lowest_one(self) -> Self;
+        fn clear_below(self, pos: u64) -> Self;
+        fn count_suffix(self, pos: u64) -> u64;
+        fn get_bit(self, pos: u64) -> bool;
+        fn set_bit(self, pos: u64) -> Self;
+        fn select0_raw(self, rank: u64) -> u64;
+        fn select1_raw(self, rank: u64) -> u64;
+
+        // Get or replace the `i`th 64-bit word of the bits or code.
+        fn word(self, i: usize) -> u64;
+        fn with_word(self, i: usize, word: u64) -> Self;
+    }
+}
+
+impl sealed::Block for u64 {
+    const BITS: u64 = SMALL_BLOCK_SIZE;
+    const ZERO: Self = 0;
+
+    #[inline]
+    fn code_length(class: u8) -> u8 {
+        ENUM_CODE_LENGTH[class as usize]
+    }
+
+    #[inline]
+    fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+        rank_acceleration::scan_block(classes, start, end)
+    }
+
+    #[inline]
+    fn encode(self, class: u8) -> Self {
+        encode(self, class).1
+    }
+
+    #[inline]
+    fn decode(self, class: u8) -> Self {
+        decode(self, class)
+    }
+
+    #[inline]
+    fn is_valid(self, class: u8) -> bool {
+        is_valid(self, class)
+    }
+
+    #[inline]
+    fn decode_bit(self, class: u8, pos: u64) -> bool {
+        decode_bit(self, class, pos)
+    }
+
+    #[inline]
+    fn rank(self, class: u8, pos: u64) -> u64 {
+        rank(self, class, pos)
+    }
+
+    #[inline]
+    fn select0(self, class: u8, rank: u64) -> u64 {
+        select0(self, class, rank)
+    }
+
+    #[inline]
+    fn select1(self, class: u8, rank: u64) -> u64 {
+        select1(self, class, rank)
+    }
+
+    #[inline]
+    fn ones(self) -> u64 {
+        self.count_ones() as u64
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> u64 {
+        self.trailing_zeros() as u64
+    }
+
+    #[inline]
+    fn clear_lowest_one(self) -> Self {
+        self & (self - 1)
+    }
+
+    #[inline]
+    fn clear_below(self, pos: u64) -> Self {
+        self & Self::MAX.wrapping_shl(pos as u32)
+    }
+
+    #[inline]
+    fn count_suffix(self, pos: u64) -> u64 {
+        (self >> pos).count_ones() as u64
+    }
+
+    #[inline]
+    fn get_bit(self, pos: u64) -> bool {
+        (self >> pos) & 1 == 1
+    }
+
+    #[inline]
+    fn set_bit(self, pos: u64) -> Self {
+        self | 1 << pos
+    }
+
+    #[inline]
+    fn select0_raw(self, rank: u64) -> u64 {
+        select1_raw(!self, rank)
+    }
+
+    #[inline]
+    fn select1_raw(self, rank: u64) -> u64 {
+        select1_raw(self, rank)
+    }
+
+    #[inline]
+    fn word(self, _i: usize) -> u64 {
+        self
+    }
+
+    #[inline]
+    fn with_word(self, _i: usize, word: u64) -> Self {
+        word
+    }
+}
+
+impl sealed::Block for u128 {
+    const BITS: u64 = wide::SMALL_BLOCK_SIZE;
+    const ZERO: Self = 0;
+
+    #[inline]
+    fn code_length(class: u8) -> u8 {
+        wide::ENUM_CODE_LENGTH[class as usize]
+    }
+
+    // There's no vectorized scan for the wide code lengths, but there are
+    // only half as many classes per large block to scan.
+    #[inline]
+    fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+        classes[start..end]
+            .iter()
+            .fold((0, 0), |(class_sum, length_sum), &class| {
+                let length = wide::ENUM_CODE_LENGTH[class as usize] as u64;
+                (class_sum + class as u64, length_sum + length)
+            })
+    }
+
+    #[inline]
+    fn encode(self, class: u8) -> Self {
+        wide::encode(self, class).1
+    }
+
+    #[inline]
+    fn decode(self, class: u8) -> Self {
+        wide::decode(self, class)
+    }
+
+    #[inline]
+    fn is_valid(self, class: u8) -> bool {
+        wide::is_valid(self, class)
+    }
+
+    #[inline]
+    fn decode_bit(self, class: u8, pos: u64) -> bool {
+        wide::decode_bit(self, class, pos)
+    }
+
+    #[inline]
+    fn rank(self, class: u8, pos: u64) -> u64 {
+        wide::rank(self, class, pos)
+    }
+
+    #[inline]
+    fn select0(self, class: u8, rank: u64) -> u64 {
+        wide::select0(self, class, rank)
+    }
+
+    #[inline]
+    fn select1(self, class: u8, rank: u64) -> u64 {
+        wide::select1(self, class, rank)
+    }
+
+    #[inline]
+    fn ones(self) -> u64 {
+        self.count_ones() as u64
+    }
+
+    #[inline]
+    fn trailing_zeros(self) -> u64 {
+        self.trailing_zeros() as u64
+    }
+
+    #[inline]
+    fn clear_lowest_one(self) -> Self {
+        self & (self - 1)
+    }
+
+    #[inline]
+    fn clear_below(self, pos: u64) -> Self {
+        self & Self::MAX.wrapping_shl(pos as u32)
+    }
+
+    #[inline]
+    fn count_suffix(self, pos: u64) -> u64 {
+        (self >> pos).count_ones() as u64
+    }
+
+    #[inline]
+    fn get_bit(self, pos: u64) -> bool {
+        (self >> pos) & 1 == 1
+    }
+
+    #[inline]
+    fn set_bit(self, pos: u64) -> Self {
+        self | 1 << pos
+    }
+
+    #[inline]
+    fn select0_raw(self, rank: u64) -> u64 {
+        wide::select1_raw(!self, rank)
+    }
+
+    #[inline]
+    fn select1_raw(self, rank: u64) -> u64 {
+        wide::select1_raw(self, rank)
+    }
+
+    #[inline]
+    fn word(self, i: usize) -> u64 {
+        (self >> (64 * i)) as u64
+    }
+
+    #[inline]
+    fn with_word(self, i: usize, word: u64) -> Self {
+        let shift = 64 * i;
+        self & !((u64::MAX as u128) << shift) | (word as u128) << shift
     }
-    debug_assert!(false, "select0 past end of codeword");
-    0
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use super::{binomial_coefficient, decode, decode_bit, encode, rank, select0, select1};
+    use super::{
+        binomial_coefficient, decode, decode_bit, encode, rank, select0, select1, select1_raw,
+        select1_raw_broadword, wide, SmallBlock,
+    };
     use crate::test_helpers::hash_u64;
+    #[cfg(not(target_arch = "wasm32"))]
     use succinct::broadword;
 
+    // `succinct` doesn't build for wasm32, so check against a bit-by-bit scan there.
+    #[cfg(target_arch = "wasm32")]
+    mod broadword {
+        pub fn select1_raw(r: usize, x: u64) -> usize {
+            (0..64)
+                .filter(|&i| (x >> i) & 1 != 0)
+                .nth(r)
+                .unwrap_or(72)
+        }
+    }
+
     fn check_roundtrip(value: u64) -> bool {
         let class = value.count_ones() as u8;
         let (_, code) = encode(value, class);
@@ -212,7 +766,7 @@ mod tests {
     #[test]
     fn test_encode() {
         for i in 0..64 {
-            check_roundtrip(std::u64::MAX << i);
+            check_roundtrip(u64::MAX << i);
         }
         check_roundtrip(0);
     }
@@ -227,6 +781,70 @@ mod tests {
         check_roundtrip(hash_u64(value))
     }
 
+    // Check decoding each bit of `value`'s code, and rank and select at each
+    // position, against a scan over its bits.
+    fn check_code<C: SmallBlock>(value: C) {
+        let class = value.ones() as u8;
+        let code = value.encode(class);
+        assert_eq!(code.decode(class), value);
+        assert!(code.is_valid(class));
+        let (mut one_rank, mut zero_rank) = (0, 0);
+        for i in 0..C::BITS {
+            let bit = value.get_bit(i);
+            assert_eq!(code.decode_bit(class, i), bit);
+            assert_eq!(code.rank(class, i), one_rank);
+            if bit {
+                assert_eq!(code.select1(class, one_rank), i);
+                one_rank += 1;
+            } else {
+                assert_eq!(code.select0(class, zero_rank), i);
+                zero_rank += 1;
+            }
+        }
+    }
+
+    // Blocks ending in a run of ones hit the `k > n` edge of the binomial
+    // table once only ones remain.
+    #[test]
+    fn test_rank_select_trailing_ones() {
+        for i in 0..64 {
+            check_code(u64::MAX << i);
+            check_code(!(u64::MAX << i));
+        }
+    }
+
+    // Cover every class, including either side of where the decoder switches
+    // from raw codes to sparse ones and to the complements of sparse ones.
+    #[test]
+    fn test_every_class() {
+        for class in 0..=64 {
+            for seed in 0..16 {
+                let mut value = 0u64;
+                let mut x = seed * 1000;
+                while value.count_ones() < class as u32 {
+                    x += 1;
+                    value |= 1 << (hash_u64(x) % 64);
+                }
+                let (_, code) = encode(value, class);
+                assert_eq!(decode(code, class), value);
+                check_code(value);
+            }
+        }
+    }
+
+    // Hashed values are mostly stored raw, so thin them out (or fill them in)
+    // to exercise the decoder on both sparse blocks and their complements.
+    #[quickcheck]
+    fn qc_sparse_and_dense(value: u64) {
+        let sparse = hash_u64(value) & hash_u64(value + 1) & hash_u64(value + 2);
+        for &value in &[sparse, !sparse, sparse & (sparse >> 7), !(sparse & (sparse >> 7))] {
+            let class = value.count_ones() as u8;
+            let (_, code) = encode(value, class);
+            assert_eq!(decode(code, class), value);
+            check_code(value);
+        }
+    }
+
     #[quickcheck]
     fn qc_decode_bit(value: u64) -> bool {
         let value = hash_u64(value);
@@ -239,6 +857,18 @@ mod tests {
         })
     }
 
+    #[quickcheck]
+    fn qc_select1_raw(value: u64) {
+        let value = hash_u64(value);
+        for &value in &[value, !value, value & (value >> 1)] {
+            for i in 0..value.count_ones() as u64 {
+                let expected = broadword::select1_raw(i as usize, value) as u64;
+                assert_eq!(select1_raw(value, i), expected);
+                assert_eq!(select1_raw_broadword(value, i), expected);
+            }
+        }
+    }
+
     #[quickcheck]
     fn qc_rank(value: u64) -> bool {
         let value = hash_u64(value);
@@ -275,6 +905,31 @@ mod tests {
         })
     }
 
+    fn hash_u128(value: u64) -> u128 {
+        let (low, high) = (hash_u64(value), hash_u64(value ^ u64::MAX));
+        low as u128 | (high as u128) << 64
+    }
+
+    #[quickcheck]
+    fn qc_wide(value: u64, sparsity: u8) {
+        // Mask out some of the bits so we cover the short codes too.
+        let value = (0..(sparsity % 4) as u64).fold(hash_u128(value), |v, i| {
+            v & hash_u128(value.wrapping_add(i + 1))
+        });
+        let class = value.count_ones() as u8;
+        let (code_len, code) = wide::encode(value, class);
+        assert!(code_len as u32 >= 128 - code.leading_zeros());
+        check_code(value);
+    }
+
+    #[test]
+    fn test_wide_trailing_ones() {
+        for i in 0..128 {
+            check_code(u128::MAX << i);
+            check_code(!(u128::MAX << i));
+        }
+    }
+
     #[test]
     fn test_binomial_coefficient_table() {
         fn lookup(table: &mut HashMap<(u8, u8), u64>, n: u8, k: u8) -> u64 {
@@ -296,15 +951,3 @@ mod tests {
         }
     }
 }
-
-// Precomputed number of bits it takes to represent a block of 64 bits where k
-// of them are set: ceil(log(binomial_coefficient(64, k))) for k in [0, 64].
-// However, note that once the code length is sufficiently long (> 46), we just
-// set it to 64 to hit the fast paths above.  It's not worth using a variable
-// length code to save a few bits when it makes computing `rank` and `select`
-// much more expensive.
-pub const ENUM_CODE_LENGTH: &[u8; 65] = &[
-    0, 6, 11, 16, 20, 23, 27, 30, 33, 35, 38, 40, 42, 44, 46, 64, 64, 64, 64, 64, 64, 64, 64, 64,
-    64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64, 64,
-    64, 64, 46, 44, 42, 40, 38, 35, 33, 30, 27, 23, 20, 16, 11, 6, 0,
-];
@@ -0,0 +1,328 @@
+//! Full-text index over a byte string built from the Burrows-Wheeler
+//! transform (BWT) of the text.  See Ferragina and Manzini, "Opportunistic
+//! Data Structures with Applications".
+//!
+//! We append a sentinel `$`, smaller than every byte, to the text and shift
+//! every byte up by one so the sentinel can be stored as symbol `0`.  The BWT
+//! is then stored in a [`WaveletMatrix`] with 9-bit symbols, whose levels are
+//! `RsDict`s by default, so counting a pattern takes `2 * 9` rank queries per pattern
+//! byte.
+//!
+//! To `locate` occurrences, we sample every `sample_rate`th entry of the
+//! suffix array (by text position), marking the sampled rows in another
+//! bitmap.
+//! From an unsampled row, we walk backwards through the text with the LF
+//! mapping until we hit a sampled row.  Similarly, `extract` starts from a
+//! sample of the inverse suffix array to the right of the range and walks
+//! backwards to its start.
+use alloc::vec;
+use alloc::vec::Vec;
+use core::iter::FromIterator;
+use core::ops::Range;
+
+use super::wavelet_matrix::WaveletMatrix;
+use super::{BitRank, RsDict};
+
+const SYMBOL_BITS: u32 = 9;
+const NUM_SYMBOLS: usize = 257;
+const DEFAULT_SAMPLE_RATE: u64 = 32;
+
+/// FM-index supporting `count`, `locate` and `extract` over a byte string.
+///
+/// ```
+/// use rsdict::FmIndex;
+///
+/// let index = FmIndex::new(b"abracadabra");
+/// assert_eq!(index.count(b"abra"), 2);
+/// assert_eq!(index.locate(b"abra"), vec![0, 7]);
+/// assert_eq!(index.extract(4..7), b"cad".to_vec());
+/// ```
+///
+/// The bitmaps can be stored in any [`BitRank`] implementation with
+/// [`FmIndex::from_text`].
+#[derive(Debug, Clone)]
+pub struct FmIndex<B = RsDict> {
+    // Length of the text, *excluding* the sentinel.
+    len: u64,
+    bwt: WaveletMatrix<B>,
+
+    // `counts[s]` is the number of symbols in the text (including the
+    // sentinel) strictly less than `s`.
+    counts: Vec<u64>,
+
+    // Suffix array samples: `sampled` marks the rows whose suffix starts at a
+    // multiple of `sample_rate`, and `sa_samples` stores their text positions
+    // in row order.
+    sample_rate: u64,
+    sampled: B,
+    sa_samples: Vec<u64>,
+
+    // Inverse suffix array samples: `isa_samples[i]` is the row of the suffix
+    // starting at `i * sample_rate`.
+    isa_samples: Vec<u64>,
+}
+
+impl FmIndex {
+    /// Build an index over `text`, sampling every 32nd suffix array entry.
+    pub fn new(text: &[u8]) -> Self {
+        Self::with_sample_rate(text, DEFAULT_SAMPLE_RATE)
+    }
+
+    /// Build an index over `text`, sampling every `sample_rate`th suffix
+    /// array entry.  Smaller rates make `locate` and `extract` faster at the
+    /// cost of more space.
+    #[inline]
+    pub fn with_sample_rate(text: &[u8], sample_rate: u64) -> Self {
+        Self::from_text(text, sample_rate)
+    }
+}
+
+impl<B: BitRank> FmIndex<B> {
+    /// Same as [`FmIndex::with_sample_rate`] but storing the bitmaps in `B`.
+    pub fn from_text(text: &[u8], sample_rate: u64) -> Self
+    where
+        B: FromIterator<bool>,
+    {
+        assert!(sample_rate > 0, "Sample rate must be positive");
+        let n = text.len();
+        let sa = suffix_array(text);
+
+        let mut counts = vec![0u64; NUM_SYMBOLS + 1];
+        let mut bwt = Vec::with_capacity(n + 1);
+        let mut sa_samples = Vec::with_capacity(n / sample_rate as usize + 1);
+        let mut isa_samples = vec![0; (n + sample_rate as usize - 1) / sample_rate as usize];
+
+        for (row, &pos) in sa.iter().enumerate() {
+            let symbol = if pos == 0 {
+                0
+            } else {
+                text[pos as usize - 1] as u64 + 1
+            };
+            bwt.push(symbol);
+            counts[symbol as usize + 1] += 1;
+
+            if pos % sample_rate == 0 {
+                sa_samples.push(pos);
+                if pos < n as u64 {
+                    isa_samples[(pos / sample_rate) as usize] = row as u64;
+                }
+            }
+        }
+        for s in 1..counts.len() {
+            counts[s] += counts[s - 1];
+        }
+
+        Self {
+            len: n as u64,
+            bwt: WaveletMatrix::from_symbols(&bwt, SYMBOL_BITS),
+            counts,
+            sample_rate,
+            sampled: sa.iter().map(|&pos| pos % sample_rate == 0).collect(),
+            sa_samples,
+            isa_samples,
+        }
+    }
+
+    /// Return the length of the indexed text.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the indexed text is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count the number of (possibly overlapping) occurrences of `pattern` in
+    /// the text.  The empty pattern occurs `len() + 1` times.
+    pub fn count(&self, pattern: &[u8]) -> u64 {
+        let (start, end) = self.backward_search(pattern);
+        end - start
+    }
+
+    /// Return the starting positions of all occurrences of `pattern` in the
+    /// text, in increasing order.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<u64> {
+        let (start, end) = self.backward_search(pattern);
+        let mut positions: Vec<u64> = (start..end).map(|row| self.suffix_at(row)).collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// Recover the bytes of the text in `range`.  Panics if the range is
+    /// out-of-bounds.
+    pub fn extract(&self, range: Range<u64>) -> Vec<u8> {
+        if range.start > range.end || range.end > self.len {
+            panic!("Out of bounds range: {:?} for length {}", range, self.len);
+        }
+        if range.start == range.end {
+            return vec![];
+        }
+
+        // Find the first sampled text position at or after the end of our
+        // range.  The suffix starting at `len` is just the sentinel, which
+        // always sorts first.
+        let mut pos = (range.end + self.sample_rate - 1) / self.sample_rate * self.sample_rate;
+        let mut row = if pos >= self.len {
+            pos = self.len;
+            0
+        } else {
+            self.isa_samples[(pos / self.sample_rate) as usize]
+        };
+
+        // Walk backwards, where the BWT symbol for the suffix at `pos` is the
+        // text byte at `pos - 1`.
+        let mut out = Vec::with_capacity((range.end - range.start) as usize);
+        while pos > range.start {
+            let symbol = self.bwt.access(row);
+            if pos <= range.end {
+                out.push((symbol - 1) as u8);
+            }
+            row = self.counts[symbol as usize] + self.bwt.rank(symbol, row);
+            pos -= 1;
+        }
+        out.reverse();
+        out
+    }
+
+    // Compute the range of rows in the suffix array prefixed by `pattern`.
+    fn backward_search(&self, pattern: &[u8]) -> (u64, u64) {
+        let (mut start, mut end) = (0, self.len + 1);
+        for &byte in pattern.iter().rev() {
+            let symbol = byte as u64 + 1;
+            let base = self.counts[symbol as usize];
+            start = base + self.bwt.rank(symbol, start);
+            end = base + self.bwt.rank(symbol, end);
+            if start >= end {
+                return (0, 0);
+            }
+        }
+        (start, end)
+    }
+
+    // Compute the suffix array entry at `row` by walking the LF mapping until
+    // we reach a sampled row.
+    fn suffix_at(&self, mut row: u64) -> u64 {
+        let mut steps = 0;
+        loop {
+            let (is_sample, sample_ix) = self.sampled.bit_and_one_rank(row);
+            if is_sample {
+                return self.sa_samples[sample_ix as usize] + steps;
+            }
+            // Since the suffix starting at zero is always sampled, we never
+            // need to step past the sentinel here.
+            let symbol = self.bwt.access(row);
+            row = self.counts[symbol as usize] + self.bwt.rank(symbol, row);
+            steps += 1;
+        }
+    }
+}
+
+// Build the suffix array of `text` with an implicit sentinel appended, so the
+// result has `text.len() + 1` entries and starts with `text.len()`.
+//
+// We use prefix doubling: after the round with step `k`, suffixes are sorted
+// by their first `2k` symbols, so `ceil(log2(n))` rounds suffice.
+fn suffix_array(text: &[u8]) -> Vec<u64> {
+    let n = text.len() + 1;
+    let mut rank: Vec<u64> = text.iter().map(|&b| b as u64 + 1).collect();
+    rank.push(0);
+    let mut sa: Vec<u64> = (0..n as u64).collect();
+    let mut next_rank = vec![0; n];
+
+    let mut k = 1;
+    loop {
+        // Ranks are shifted up by one so that running off the end of the
+        // text sorts first.
+        let key = |i: u64| {
+            let i = i as usize;
+            let second = if i + k < n { rank[i + k] + 1 } else { 0 };
+            (rank[i], second)
+        };
+        sa.sort_unstable_by_key(|&i| key(i));
+
+        next_rank[sa[0] as usize] = 0;
+        for w in 1..n {
+            let bump = (key(sa[w - 1]) != key(sa[w])) as u64;
+            next_rank[sa[w] as usize] = next_rank[sa[w - 1] as usize] + bump;
+        }
+        core::mem::swap(&mut rank, &mut next_rank);
+
+        if rank[sa[n - 1] as usize] == n as u64 - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::{suffix_array, FmIndex};
+    use crate::test_helpers::hash_u64;
+    use crate::PlainRsDict;
+
+    // Use a small alphabet so patterns actually repeat.
+    fn test_text(seeds: &[u64], alphabet: u8) -> Vec<u8> {
+        seeds
+            .iter()
+            .take(12)
+            .flat_map(|&s| (0..8).map(move |i| hash_u64(s.wrapping_add(i))))
+            .map(|x| b'a' + (x % alphabet as u64) as u8)
+            .collect()
+    }
+
+    fn naive_locate(text: &[u8], pattern: &[u8]) -> Vec<u64> {
+        (0..=text.len())
+            .filter(|&i| text[i..].starts_with(pattern))
+            .map(|i| i as u64)
+            .collect()
+    }
+
+    #[quickcheck]
+    fn qc_suffix_array(seeds: Vec<u64>) {
+        let text = test_text(&seeds, 3);
+        let sa = suffix_array(&text);
+        let mut expected: Vec<u64> = (0..=text.len() as u64).collect();
+        expected.sort_by_key(|&i| &text[i as usize..]);
+        assert_eq!(sa, expected);
+    }
+
+    #[quickcheck]
+    fn qc_count_and_locate(seeds: Vec<u64>, sample_rate: u8) {
+        let text = test_text(&seeds, 4);
+        let index = FmIndex::with_sample_rate(&text, sample_rate as u64 % 16 + 1);
+        let plain = FmIndex::<PlainRsDict>::from_text(&text, sample_rate as u64 % 16 + 1);
+        assert_eq!(index.len(), text.len());
+        for start in (0..text.len()).step_by(7) {
+            for len in 1..4 {
+                let pattern = &text[start..(start + len).min(text.len())];
+                let expected = naive_locate(&text, pattern);
+                assert_eq!(index.count(pattern), expected.len() as u64);
+                assert_eq!(index.locate(pattern), expected);
+                assert_eq!(plain.locate(pattern), expected);
+            }
+        }
+        assert_eq!(index.count(b""), text.len() as u64 + 1);
+        assert_eq!(index.count(b"z"), 0);
+        assert!(index.locate(b"abcz").is_empty());
+    }
+
+    #[quickcheck]
+    fn qc_extract(seeds: Vec<u64>, sample_rate: u8) {
+        let text = test_text(&seeds, 26);
+        let index = FmIndex::with_sample_rate(&text, sample_rate as u64 % 16 + 1);
+        let n = text.len() as u64;
+        assert_eq!(index.extract(0..n), text);
+        for start in (0..=n).step_by(3) {
+            for end in [start, start + 1, start + 5, start + 40] {
+                let end = end.min(n);
+                let extracted = index.extract(start..end);
+                assert_eq!(&extracted[..], &text[start as usize..end as usize]);
+            }
+        }
+    }
+}
@@ -1,8 +1,213 @@
+//! Content-based equality, hashing, ordering and fingerprints for `RsDict`.
+//!
+//! Each small block's enumerative code is determined by its bits, so two
+//! dictionaries hold the same bitmap exactly when their classes, codes and
+//! last blocks match, regardless of block sizes or which select indices they
+//! keep.  Equality, hashing and fingerprints use these directly without
+//! decoding anything, while ordering decodes a 64-bit word at a time.
 use super::*;
-use std::hash::{Hash, Hasher};
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
 
-impl Hash for RsDict {
+const FINGERPRINT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// The 64-bit finalizer from MurmurHash3.
+#[inline]
+fn fmix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Return a 64-bit fingerprint of the bitmap's contents that's stable across platforms,
+    /// processes, block sizes and select index settings, unlike `Hash` with `std`'s randomly
+    /// seeded `DefaultHasher`.  Equal bitmaps always have the same fingerprint.  It's meant for
+    /// detecting changes and corruption, not for resisting deliberate collisions.
+    ///
+    /// The fingerprint is computed over the same fields as [`RsDict::to_bytes`] stores, read as a
+    /// sequence of 64-bit words:
+    ///
+    /// 1. the number of bits,
+    /// 2. the small block classes, packed eight to a word in little-endian order with the last
+    ///    word padded with zeros,
+    /// 3. the enumerative code words,
+    /// 4. the last block's bits, which is zero for an empty bitmap.
+    ///
+    /// Starting with `h = 0x9e3779b97f4a7c15`, each word `w` updates `h = fmix64(h ^ w)`, where
+    /// `fmix64` is MurmurHash3's 64-bit finalizer, and the fingerprint is the final `h`.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// let r = RsDict::from_blocks([0xdead_beef, 0, u64::MAX].iter().copied());
+    /// let mut pushed = RsDict::<512, 2048>::with_block_sizes();
+    /// for i in 0..r.len() as u64 {
+    ///     pushed.push(r.get_bit(i));
+    /// }
+    /// assert_eq!(r.fingerprint(), pushed.fingerprint());
+    ///
+    /// pushed.push(false);
+    /// assert_ne!(r.fingerprint(), pushed.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        let mut h = fmix64(FINGERPRINT_SEED ^ self.len);
+        for chunk in self.sb_classes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            h = fmix64(h ^ u64::from_le_bytes(word));
+        }
+        for &word in &self.sb_indices.buf {
+            h = fmix64(h ^ word);
+        }
+        fmix64(h ^ self.last_block.bits)
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> PartialEq
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.num_ones == other.num_ones
+            && self.last_block.bits == other.last_block.bits
+            && self.sb_classes == other.sb_classes
+            && self.sb_indices == other.sb_indices
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> Eq
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> Hash
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.iter().for_each(|x| x.hash(state));
+        // Include the length so bitmaps that only differ in trailing zeros
+        // hash differently.
+        self.len.hash(state);
+        self.sb_classes.hash(state);
+        self.sb_indices.buf.hash(state);
+        self.last_block.bits.hash(state);
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> PartialOrd
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Bitmaps are ordered lexicographically by their bits, starting at index 0, with `false < true`
+/// and a bitmap ordered before any longer bitmap it's a prefix of.  This is the same order as
+/// comparing them as `Vec<bool>`s.
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> Ord
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Bits past the end of the last block are zero, so the first
+        // difference in the decoded words is either a real difference between
+        // the bitmaps or past the end of the shorter one.
+        let common_len = self.len.min(other.len);
+        for (i, (a, b)) in self.blocks().zip(other.blocks()).enumerate() {
+            let diff = a ^ b;
+            if diff == 0 {
+                continue;
+            }
+            let pos = i as u64 * SMALL_BLOCK_SIZE + diff.trailing_zeros() as u64;
+            if pos >= common_len {
+                break;
+            }
+            return if (a >> diff.trailing_zeros()) & 1 == 1 {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        self.len.cmp(&other.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::hash_u64;
+    use crate::{RsDict, RsDictBuilder};
+    use alloc::vec::Vec;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash_of(r: &RsDict<128, 256>) -> u64 {
+        let mut h = DefaultHasher::new();
+        r.hash(&mut h);
+        h.finish()
+    }
+
+    // Small inputs make equal and prefix-related bitmaps likely.
+    fn to_bits(bits: Vec<u8>) -> Vec<bool> {
+        bits.into_iter()
+            .flat_map(|b| {
+                let b = hash_u64(b as u64 % 4);
+                (0..(b % 80)).map(move |i| (b >> (i % 64)) & 1 == 1)
+            })
+            .collect()
+    }
+
+    #[quickcheck]
+    fn qc_eq_hash_ord(a: Vec<u8>, b: Vec<u8>) {
+        let (a, b) = (to_bits(a), to_bits(b));
+        let ra: RsDict<128, 256> = a.iter().copied().collect();
+        let mut rb = RsDictBuilder::<128, 256>::with_block_sizes()
+            .select1_index(false)
+            .build();
+        for &bit in &b {
+            rb.push(bit);
+        }
+
+        assert_eq!(ra == rb, a == b);
+        assert_eq!(ra.cmp(&rb), a.cmp(&b));
+        assert_eq!(rb.cmp(&ra), b.cmp(&a));
+        assert_eq!(ra.partial_cmp(&rb), a.partial_cmp(&b));
+        if a == b {
+            assert_eq!(hash_of(&ra), hash_of(&rb));
+            assert_eq!(ra.fingerprint(), rb.fingerprint());
+        }
+
+        let mut ra2 = ra.clone();
+        assert_eq!(hash_of(&ra), hash_of(&ra2));
+        ra2.push(false);
+        assert!(ra < ra2);
+        assert_ne!(ra, ra2);
+        assert_ne!(hash_of(&ra), hash_of(&ra2));
+        assert_ne!(ra.fingerprint(), ra2.fingerprint());
+    }
+
+    // The fingerprint is documented, so it mustn't change between releases.
+    #[test]
+    fn test_fingerprint_stable() {
+        assert_eq!(RsDict::new().fingerprint(), 0x6393_d51c_06c6_18dc);
+        let r: RsDict = [true, false, true].iter().copied().collect();
+        assert_eq!(r.fingerprint(), 0xa0d1_f34c_c1f3_ba28);
+
+        let mut x = 0x2545_f491_4f6c_dd1d_u64;
+        let r = RsDict::from_blocks((0..100).map(|i| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            if i % 2 == 0 {
+                x
+            } else {
+                x & (x >> 7)
+            }
+        }));
+        assert_eq!(r.fingerprint(), 0x598a_0558_fa4d_287d);
     }
-}
\ No newline at end of file
+}
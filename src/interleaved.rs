@@ -0,0 +1,363 @@
+//! Alternative layout of `RsDict` with each large block's metadata packed into
+//! a single cache line.
+//!
+//! A rank query on `RsDict` reads from `large_blocks`, `sb_classes` and
+//! `sb_indices`, which are three separate allocations, so it takes at least
+//! three cache misses on cold data.  `InterleavedRsDict` instead stores each
+//! large block's rank, code pointer and the classes of its 16 small blocks in
+//! one 64-byte aligned `Record`, so a query takes one miss for the record and
+//! one for the code.  The cost is space: a `Record` takes 64 bytes per 1024
+//! bits, compared to 32 bytes for `RsDict`'s `LargeBlock` and classes.
+//!
+//! The dictionary is static and built from an `RsDict` with the default block
+//! sizes, reusing its codes and select indices.
+use alloc::vec::Vec;
+use core::mem;
+
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, SMALL_BLOCK_SIZE};
+use super::enum_code::{self, ENUM_CODE_LENGTH};
+use super::rank_acceleration;
+use super::{rank_by_bit, RsDict, VarintBuffer};
+
+const LARGE_BLOCK_SIZE: u64 = DEFAULT_LARGE_BLOCK_SIZE;
+const SELECT_BLOCK_SIZE: u64 = DEFAULT_SELECT_BLOCK_SIZE;
+const SMALL_BLOCK_PER_LARGE_BLOCK: usize = (LARGE_BLOCK_SIZE / SMALL_BLOCK_SIZE) as usize;
+
+#[derive(Debug, Clone, PartialEq)]
+#[repr(C, align(64))]
+struct Record {
+    rank: u64,
+    pointer: u64,
+    // Only the first `SMALL_BLOCK_PER_LARGE_BLOCK` classes are used.  The rest
+    // are zero padding, so `rank_acceleration::scan_block` can always read a
+    // whole vector without leaving the record.
+    classes: [u8; 48],
+}
+
+const _: () = assert!(mem::size_of::<Record>() == 64);
+
+/// Rank and select dictionary with cache-line interleaved large block
+/// metadata, for latency-bound queries over large bitmaps.
+///
+/// ```
+/// use rsdict::{InterleavedRsDict, RsDict};
+///
+/// let mut r = RsDict::from_blocks(vec![0b1011, 0].into_iter());
+/// r.push(true);
+/// let r = InterleavedRsDict::from(r);
+/// assert_eq!(r.len(), 129);
+/// assert_eq!(r.rank(128, true), 3);
+/// assert_eq!(r.select1(3), Some(128));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterleavedRsDict {
+    len: u64,
+    num_ones: u64,
+    num_zeros: u64,
+
+    records: Vec<Record>,
+    // Unlike `RsDict`, the last (possibly partial) small block is encoded here
+    // along with the others.
+    sb_indices: VarintBuffer,
+
+    select_one_inds: Vec<u64>,
+    select_zero_inds: Vec<u64>,
+    // Whether the select indices above are maintained, as set with
+    // `RsDictBuilder` on the source dictionary.
+    select1_index: bool,
+    select0_index: bool,
+}
+
+impl From<RsDict> for InterleavedRsDict {
+    fn from(r: RsDict) -> Self {
+        let mut sb_classes = r.sb_classes;
+        let mut sb_indices = r.sb_indices;
+        if r.len > 0 {
+            let sb_class = r.last_block.num_ones as u8;
+            let (code_len, code) = enum_code::encode(r.last_block.bits, sb_class);
+            sb_classes.push(sb_class);
+            sb_indices.push(code_len as usize, code);
+        }
+
+        let num_records =
+            (sb_classes.len() + SMALL_BLOCK_PER_LARGE_BLOCK - 1) / SMALL_BLOCK_PER_LARGE_BLOCK;
+        let mut records = Vec::with_capacity(num_records);
+        let (mut rank, mut pointer) = (0, 0);
+        for chunk in sb_classes.chunks(SMALL_BLOCK_PER_LARGE_BLOCK) {
+            let mut classes = [0; 48];
+            classes[..chunk.len()].copy_from_slice(chunk);
+            records.push(Record {
+                rank,
+                pointer,
+                classes,
+            });
+            for &sb_class in chunk {
+                rank += sb_class as u64;
+                pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+            }
+        }
+
+        Self {
+            len: r.len,
+            num_ones: r.num_ones,
+            num_zeros: r.num_zeros,
+            records,
+            sb_indices,
+            select_one_inds: r.select_one_inds,
+            select_zero_inds: r.select_zero_inds,
+            select1_index: r.select1_index,
+            select0_index: r.select0_index,
+        }
+    }
+}
+
+impl InterleavedRsDict {
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit blocks.
+    pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
+        RsDict::from_blocks(blocks).into()
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    #[inline]
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let record = &self.records[(pos / LARGE_BLOCK_SIZE) as usize];
+        let sblock = ((pos % LARGE_BLOCK_SIZE) / SMALL_BLOCK_SIZE) as usize;
+        let (class_sum, length_sum) = rank_acceleration::scan_block(&record.classes, 0, sblock);
+        let mut rank = record.rank + class_sum;
+        if pos % SMALL_BLOCK_SIZE != 0 {
+            let sb_class = record.classes[sblock];
+            let code = self.read_sb_index(record.pointer + length_sum, sb_class);
+            rank += enum_code::rank(code, sb_class, pos % SMALL_BLOCK_SIZE);
+        }
+        rank_by_bit(rank, pos, bit)
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    #[inline]
+    pub fn get_bit(&self, pos: u64) -> bool {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let record = &self.records[(pos / LARGE_BLOCK_SIZE) as usize];
+        let sblock = ((pos % LARGE_BLOCK_SIZE) / SMALL_BLOCK_SIZE) as usize;
+        let (_, length_sum) = rank_acceleration::scan_block(&record.classes, 0, sblock);
+        let sb_class = record.classes[sblock];
+        let code = self.read_sb_index(record.pointer + length_sum, sb_class);
+        enum_code::decode_bit(code, sb_class, pos % SMALL_BLOCK_SIZE)
+    }
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    #[inline]
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        if bit {
+            self.select1(rank)
+        } else {
+            self.select0(rank)
+        }
+    }
+
+    /// Specialized version of [`InterleavedRsDict::select`] for finding positions of zeros.
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        if rank >= self.num_zeros {
+            return None;
+        }
+        let lblock = if self.select0_index {
+            let lb_start = self.select_zero_inds[(rank / SELECT_BLOCK_SIZE) as usize] as usize;
+            self.records[lb_start..]
+                .iter()
+                .enumerate()
+                .position(|(i, r)| rank < (lb_start + i) as u64 * LARGE_BLOCK_SIZE - r.rank)
+                .map(|i| lb_start + i - 1)
+                .unwrap_or(self.records.len() - 1)
+        } else {
+            let zeros_before = |i: usize| i as u64 * LARGE_BLOCK_SIZE - self.records[i].rank;
+            let (mut lo, mut hi) = (0, self.records.len());
+            while hi - lo > 1 {
+                let mid = lo + (hi - lo) / 2;
+                if zeros_before(mid) <= rank {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            lo
+        };
+        let record = &self.records[lblock];
+
+        // Since `rank < num_zeros`, we'll stop before reaching the padding
+        // zeros at the end of the last small block.
+        let mut pointer = record.pointer;
+        let mut remaining = rank - (lblock as u64 * LARGE_BLOCK_SIZE - record.rank);
+        for (i, &sb_class) in record.classes[..SMALL_BLOCK_PER_LARGE_BLOCK]
+            .iter()
+            .enumerate()
+        {
+            let sb_zeros = SMALL_BLOCK_SIZE - sb_class as u64;
+            if remaining < sb_zeros {
+                let code = self.read_sb_index(pointer, sb_class);
+                let sb_rank = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK + i) as u64 * SMALL_BLOCK_SIZE;
+                return Some(sb_rank + enum_code::select0(code, sb_class, remaining));
+            }
+            remaining -= sb_zeros;
+            pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+        }
+        panic!("Ran out of small blocks when iterating over rank");
+    }
+
+    /// Specialized version of [`InterleavedRsDict::select`] for finding positions of ones.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        if rank >= self.num_ones {
+            return None;
+        }
+        let lblock = if self.select1_index {
+            let lb_start = self.select_one_inds[(rank / SELECT_BLOCK_SIZE) as usize] as usize;
+            self.records[lb_start..]
+                .iter()
+                .position(|r| rank < r.rank)
+                .map(|i| lb_start + i - 1)
+                .unwrap_or(self.records.len() - 1)
+        } else {
+            self.records.partition_point(|r| r.rank <= rank) - 1
+        };
+        let record = &self.records[lblock];
+
+        let mut pointer = record.pointer;
+        let mut remaining = rank - record.rank;
+        for (i, &sb_class) in record.classes[..SMALL_BLOCK_PER_LARGE_BLOCK]
+            .iter()
+            .enumerate()
+        {
+            let sb_ones = sb_class as u64;
+            if remaining < sb_ones {
+                let code = self.read_sb_index(pointer, sb_class);
+                let sb_rank = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK + i) as u64 * SMALL_BLOCK_SIZE;
+                return Some(sb_rank + enum_code::select1(code, sb_class, remaining));
+            }
+            remaining -= sb_ones;
+            pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+        }
+        panic!("Ran out of small blocks when iterating over rank");
+    }
+
+    /// Return the length of the underlying bitmap.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the underlying bitmap is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count the number of set bits in the underlying bitmap.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.num_ones as usize
+    }
+
+    /// Count the number of unset bits in the underlying bitmap.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        self.num_zeros as usize
+    }
+
+    /// Return an iterator over the positions of the ones in the underlying bitmap.  Each small
+    /// block is decoded once, as the iterator reaches it.
+    #[inline]
+    pub fn iter(&self) -> InterleavedIterOnes<'_> {
+        InterleavedIterOnes {
+            dict: self,
+            num_sb: ((self.len + SMALL_BLOCK_SIZE - 1) / SMALL_BLOCK_SIZE) as usize,
+            next_sb: 0,
+            pointer: 0,
+            current: 0,
+        }
+    }
+
+    #[inline]
+    fn read_sb_index(&self, ptr: u64, sb_class: u8) -> u64 {
+        let code_len = ENUM_CODE_LENGTH[sb_class as usize];
+        self.sb_indices.get(ptr as usize, code_len as usize)
+    }
+}
+
+/// Iterator over the positions of the ones in an `InterleavedRsDict`.
+#[derive(Debug, Clone)]
+pub struct InterleavedIterOnes<'a> {
+    dict: &'a InterleavedRsDict,
+    num_sb: usize,
+    // Index of the next small block to decode, and where its code starts.
+    next_sb: usize,
+    pointer: u64,
+    // Ones of small block `next_sb - 1` we haven't returned yet.
+    current: u64,
+}
+
+impl<'a> Iterator for InterleavedIterOnes<'a> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        // Since the last small block is encoded along with the others and
+        // padded with zeros, every small block can be handled the same way.
+        while self.current == 0 {
+            if self.next_sb == self.num_sb {
+                return None;
+            }
+            let record = &self.dict.records[self.next_sb / SMALL_BLOCK_PER_LARGE_BLOCK];
+            let sb_class = record.classes[self.next_sb % SMALL_BLOCK_PER_LARGE_BLOCK];
+            let code = self.dict.read_sb_index(self.pointer, sb_class);
+            self.current = enum_code::decode(code, sb_class);
+            self.pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+            self.next_sb += 1;
+        }
+        let bit = self.current.trailing_zeros() as u64;
+        self.current &= self.current - 1;
+        Some((self.next_sb - 1) as u64 * SMALL_BLOCK_SIZE + bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::InterleavedRsDict;
+    use crate::{RsDict, RsDictBuilder};
+
+    #[quickcheck]
+    fn qc_interleaved(blocks: Vec<u64>, tail: Vec<bool>) {
+        let mut r = RsDict::from_blocks(blocks.iter().copied());
+        let mut unindexed = RsDictBuilder::new()
+            .select1_index(false)
+            .select0_index(false)
+            .from_blocks(blocks.into_iter());
+        for &bit in &tail {
+            r.push(bit);
+            unindexed.push(bit);
+        }
+        let interleaved = InterleavedRsDict::from(r.clone());
+        let without_index = InterleavedRsDict::from(unindexed);
+        assert_eq!(interleaved.len(), r.len());
+        assert_eq!(interleaved.count_ones(), r.count_ones());
+
+        for i in 0..r.len() as u64 {
+            assert_eq!(interleaved.get_bit(i), r.get_bit(i));
+            assert_eq!(interleaved.rank(i, true), r.rank(i, true));
+            assert_eq!(interleaved.rank(i, false), r.rank(i, false));
+        }
+        for i in 0..=r.count_ones() as u64 {
+            assert_eq!(interleaved.select1(i), r.select1(i));
+            assert_eq!(without_index.select1(i), r.select1(i));
+        }
+        for i in 0..=r.count_zeros() as u64 {
+            assert_eq!(interleaved.select0(i), r.select0(i));
+            assert_eq!(without_index.select0(i), r.select0(i));
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Conversions between `RsDict` and other crates' bitsets, each behind a
+//! cargo feature named after the crate.
+//!
+//! Conversions go through the other crate's word storage (or sorted
+//! positions, for Roaring) and `RsDict::from_blocks` rather than pushing one
+//! bit at a time.  Bit `i` of the bitset is always bit `i` of the
+//! dictionary.  As with `RsDict::from_blocks`, conversions into an `RsDict`
+//! use the default block sizes.
+
+#[cfg(feature = "bitvec")]
+mod bitvec_impls {
+    use crate::from_padded_blocks;
+    use crate::RsDict;
+    use bitvec::field::BitField;
+    use bitvec::order::Lsb0;
+    use bitvec::slice::BitSlice;
+    use bitvec::store::BitStore;
+    use bitvec::vec::BitVec;
+
+    /// Only `Lsb0` slices are supported, since that's the order `RsDict` uses within a block.
+    impl<T: BitStore> From<&BitSlice<T, Lsb0>> for RsDict {
+        fn from(bits: &BitSlice<T, Lsb0>) -> Self {
+            let blocks = bits.chunks(64).map(|chunk| chunk.load_le::<u64>());
+            from_padded_blocks(blocks, bits.len())
+        }
+    }
+
+    impl<T: BitStore> From<&BitVec<T, Lsb0>> for RsDict {
+        #[inline]
+        fn from(bits: &BitVec<T, Lsb0>) -> Self {
+            Self::from(bits.as_bitslice())
+        }
+    }
+
+    impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+        From<&RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>> for BitVec<u64, Lsb0>
+    {
+        fn from(r: &RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>) -> Self {
+            let mut bits = BitVec::from_vec(r.blocks().collect());
+            bits.truncate(r.len());
+            bits
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use alloc::vec::Vec;
+        use crate::RsDict;
+        use bitvec::order::Lsb0;
+        use bitvec::vec::BitVec;
+
+        #[quickcheck]
+        fn qc_bitvec(bits: Vec<bool>, offset: u8) {
+            let bv: BitVec<u32, Lsb0> = bits.iter().collect();
+            let offset = (offset as usize).min(bits.len());
+            let r = RsDict::from(&bv[offset..]);
+            assert_eq!(r.len(), bits.len() - offset);
+            for (i, &bit) in bits[offset..].iter().enumerate() {
+                assert_eq!(r.get_bit(i as u64), bit);
+            }
+
+            let r = RsDict::from(&bv);
+            let round_trip = BitVec::<u64, Lsb0>::from(&r);
+            assert_eq!(round_trip.iter().by_vals().collect::<Vec<_>>(), bits);
+        }
+    }
+}
+
+#[cfg(feature = "fixedbitset")]
+mod fixedbitset_impls {
+    use crate::from_padded_blocks;
+    use crate::RsDict;
+    use fixedbitset::{Block, FixedBitSet};
+
+    const BLOCK_BITS: u32 = Block::BITS;
+    const BLOCKS_PER_U64: usize = (64 / BLOCK_BITS) as usize;
+
+    impl From<&FixedBitSet> for RsDict {
+        fn from(bits: &FixedBitSet) -> Self {
+            // `FixedBitSet` stores `usize` blocks, so combine them into `u64`s
+            // on 32-bit targets.
+            let blocks = bits.as_slice().chunks(BLOCKS_PER_U64).map(|chunk| {
+                chunk.iter().enumerate().fold(0u64, |acc, (i, &b)| {
+                    acc | (b as u64) << (i as u32 * BLOCK_BITS)
+                })
+            });
+            from_padded_blocks(blocks, bits.len())
+        }
+    }
+
+    impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+        From<&RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>> for FixedBitSet
+    {
+        fn from(r: &RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>) -> Self {
+            let blocks = r.blocks().flat_map(|block| {
+                (0..BLOCKS_PER_U64).map(move |i| (block >> (i as u32 * BLOCK_BITS)) as Block)
+            });
+            FixedBitSet::with_capacity_and_blocks(r.len(), blocks)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use alloc::vec::Vec;
+        use crate::RsDict;
+        use fixedbitset::FixedBitSet;
+
+        #[quickcheck]
+        fn qc_fixedbitset(bits: Vec<bool>) {
+            let mut set = FixedBitSet::with_capacity(bits.len());
+            for (i, &bit) in bits.iter().enumerate() {
+                set.set(i, bit);
+            }
+            let r = RsDict::from(&set);
+            assert_eq!(r.len(), bits.len());
+            assert_eq!(r.count_ones(), set.count_ones(..));
+            for (i, &bit) in bits.iter().enumerate() {
+                assert_eq!(r.get_bit(i as u64), bit);
+            }
+            assert_eq!(FixedBitSet::from(&r), set);
+        }
+    }
+}
+
+#[cfg(feature = "roaring")]
+mod roaring_impls {
+    use crate::from_padded_blocks;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use crate::RsDict;
+    use roaring::{RoaringBitmap, RoaringTreemap};
+    use std::io;
+
+    // Constants from the portable Roaring format, see
+    // https://github.com/RoaringBitmap/RoaringFormatSpec.
+    const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+    const SERIAL_COOKIE: u32 = 12347;
+    const NO_OFFSET_THRESHOLD: usize = 4;
+    const ARRAY_LIMIT: usize = 4096;
+    const BITMAP_BYTES: usize = 8192;
+    // Each container covers 2^16 bits, i.e. 1024 small blocks.
+    const BLOCKS_PER_CONTAINER: usize = 1024;
+
+    // Build a dictionary of length `len` from the sorted positions of its ones.
+    fn from_sorted_positions(positions: impl Iterator<Item = u64>, len: u64) -> RsDict {
+        let mut positions = positions.peekable();
+        let num_blocks = len / 64 + (len % 64 != 0) as u64;
+        let blocks = (0..num_blocks).map(|i| {
+            let mut block = 0;
+            while let Some(&pos) = positions.peek() {
+                if pos / 64 > i {
+                    break;
+                }
+                block |= 1 << (pos % 64);
+                positions.next();
+            }
+            block
+        });
+        from_padded_blocks(blocks, len as usize)
+    }
+
+    /// The dictionary's length is one past the bitmap's maximum.
+    impl From<&RoaringBitmap> for RsDict {
+        fn from(bitmap: &RoaringBitmap) -> Self {
+            let len = bitmap.max().map(|max| max as u64 + 1).unwrap_or(0);
+            from_sorted_positions(bitmap.iter().map(|pos| pos as u64), len)
+        }
+    }
+
+    /// The dictionary's length is one past the treemap's maximum.  Panics if the treemap contains
+    /// `u64::MAX`, since that length doesn't fit in a `u64`.
+    impl From<&RoaringTreemap> for RsDict {
+        fn from(treemap: &RoaringTreemap) -> Self {
+            let len = treemap.max().map_or(0, |max| {
+                max.checked_add(1)
+                    .expect("RoaringTreemap containing u64::MAX is too long for an RsDict")
+            });
+            from_sorted_positions(treemap.iter(), len)
+        }
+    }
+
+    // What we need to know about a container to write the header.
+    struct ContainerInfo {
+        key: u16,
+        is_run: bool,
+        cardinality: usize,
+        num_bytes: usize,
+    }
+
+    enum Container {
+        Array(Vec<u16>),
+        Bitmap(Vec<u64>),
+        // Runs of ones as `(start, length - 1)`.
+        Run(Vec<(u16, u16)>),
+    }
+
+    impl Container {
+        // Pick the smallest container for a chunk of up to `BLOCKS_PER_CONTAINER` blocks.
+        fn new(blocks: &[u64]) -> Self {
+            let mut cardinality = 0;
+            let mut runs: Vec<(u16, u16)> = vec![];
+            let mut run_end = None;
+            for (i, &block) in blocks.iter().enumerate() {
+                // Class 0 blocks end any open run, and class 64 blocks extend
+                // or start one, without looking at individual bits.
+                let base = i as u32 * 64;
+                if block == 0 {
+                    run_end = None;
+                    continue;
+                }
+                if block == u64::MAX {
+                    cardinality += 64;
+                    match (run_end, runs.last_mut()) {
+                        (Some(prev_end), Some(run)) if prev_end == base => run.1 += 64,
+                        _ => runs.push((base as u16, 63)),
+                    }
+                    run_end = Some(base + 64);
+                    continue;
+                }
+                cardinality += block.count_ones() as usize;
+                let (mut rest, mut offset) = (block, 0);
+                while rest != 0 {
+                    let zeros = rest.trailing_zeros();
+                    rest >>= zeros;
+                    offset += zeros;
+                    let ones = (!rest).trailing_zeros();
+                    rest = rest.checked_shr(ones).unwrap_or(0);
+
+                    let (start, end) = (base + offset, base + offset + ones);
+                    match (run_end, runs.last_mut()) {
+                        (Some(prev_end), Some(run)) if prev_end == start => {
+                            run.1 += ones as u16;
+                        }
+                        _ => runs.push((start as u16, (ones - 1) as u16)),
+                    }
+                    run_end = if end % 64 == 0 { Some(end) } else { None };
+                    offset += ones;
+                }
+            }
+
+            let run_bytes = 2 + 4 * runs.len();
+            let other_bytes = if cardinality <= ARRAY_LIMIT {
+                2 * cardinality
+            } else {
+                BITMAP_BYTES
+            };
+            if run_bytes < other_bytes {
+                Container::Run(runs)
+            } else if cardinality <= ARRAY_LIMIT {
+                let mut values = Vec::with_capacity(cardinality);
+                for (i, &block) in blocks.iter().enumerate() {
+                    let mut rest = block;
+                    while rest != 0 {
+                        values.push((i as u32 * 64 + rest.trailing_zeros()) as u16);
+                        rest &= rest - 1;
+                    }
+                }
+                Container::Array(values)
+            } else {
+                let mut words = blocks.to_vec();
+                words.resize(BLOCKS_PER_CONTAINER, 0);
+                Container::Bitmap(words)
+            }
+        }
+
+        fn cardinality(&self) -> usize {
+            match self {
+                Container::Array(values) => values.len(),
+                Container::Bitmap(words) => words.iter().map(|w| w.count_ones() as usize).sum(),
+                Container::Run(runs) => runs.iter().map(|&(_, len)| len as usize + 1).sum(),
+            }
+        }
+
+        fn num_bytes(&self) -> usize {
+            match self {
+                Container::Array(values) => 2 * values.len(),
+                Container::Bitmap(..) => BITMAP_BYTES,
+                Container::Run(runs) => 2 + 4 * runs.len(),
+            }
+        }
+
+        fn write_into<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+            match self {
+                Container::Array(values) => {
+                    for &value in values {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                Container::Bitmap(words) => {
+                    for &word in words {
+                        writer.write_all(&word.to_le_bytes())?;
+                    }
+                }
+                Container::Run(runs) => {
+                    writer.write_all(&(runs.len() as u16).to_le_bytes())?;
+                    for &(start, len) in runs {
+                        writer.write_all(&start.to_le_bytes())?;
+                        writer.write_all(&len.to_le_bytes())?;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+        RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+    {
+        /// Serialize the positions of the ones into the portable Roaring format, which is
+        /// compatible with `RoaringBitmap::deserialize_from` and the C, Java and Go
+        /// implementations.  Each container is written as a run, array or bitmap container,
+        /// whichever is smallest.  Returns an error if the dictionary has more than 2^32 bits.
+        ///
+        /// ```
+        /// use rsdict::RsDict;
+        ///
+        /// // A million ones take a few hundred bytes as run containers, rather than 128 KB as bitmaps.
+        /// let r = RsDict::from_blocks(std::iter::repeat(!0).take(1_000_000 / 64));
+        /// let mut bytes = vec![];
+        /// r.serialize_roaring_into(&mut bytes).unwrap();
+        /// assert!(bytes.len() < 300);
+        /// ```
+        pub fn serialize_roaring_into<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+            if self.len > 1 << 32 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Roaring bitmaps only hold 32-bit positions",
+                ));
+            }
+            // A container can take 8 KiB, so rather than holding all of them,
+            // summarize them first and then decode them again to write them.
+            let num_sb = self.sb_classes.len() + (self.len > 0) as usize;
+            let num_containers = (num_sb + BLOCKS_PER_CONTAINER - 1) / BLOCKS_PER_CONTAINER;
+            let mut blocks = Vec::with_capacity(BLOCKS_PER_CONTAINER);
+            let mut containers = vec![];
+            for key in 0..num_containers {
+                if self.roaring_container_blocks(key, &mut blocks) {
+                    let c = Container::new(&blocks);
+                    containers.push(ContainerInfo {
+                        key: key as u16,
+                        is_run: matches!(c, Container::Run(..)),
+                        cardinality: c.cardinality(),
+                        num_bytes: c.num_bytes(),
+                    });
+                }
+            }
+            let size = containers.len();
+            let has_runs = containers.iter().any(|c| c.is_run);
+
+            let header_bytes = if has_runs {
+                writer.write_all(
+                    &(SERIAL_COOKIE | ((size as u32).wrapping_sub(1) << 16)).to_le_bytes(),
+                )?;
+                let mut run_flags = vec![0u8; (size + 7) / 8];
+                for (i, c) in containers.iter().enumerate() {
+                    if c.is_run {
+                        run_flags[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                writer.write_all(&run_flags)?;
+                let offsets = if size >= NO_OFFSET_THRESHOLD {
+                    4 * size
+                } else {
+                    0
+                };
+                4 + run_flags.len() + 4 * size + offsets
+            } else {
+                writer.write_all(&SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes())?;
+                writer.write_all(&(size as u32).to_le_bytes())?;
+                8 + 8 * size
+            };
+
+            for c in &containers {
+                writer.write_all(&c.key.to_le_bytes())?;
+                writer.write_all(&((c.cardinality - 1) as u16).to_le_bytes())?;
+            }
+            if !has_runs || size >= NO_OFFSET_THRESHOLD {
+                let mut offset = header_bytes;
+                for c in &containers {
+                    writer.write_all(&(offset as u32).to_le_bytes())?;
+                    offset += c.num_bytes;
+                }
+            }
+            for c in &containers {
+                self.roaring_container_blocks(c.key as usize, &mut blocks);
+                Container::new(&blocks).write_into(&mut writer)?;
+            }
+            Ok(())
+        }
+
+        // Decode the small blocks in the `key`th container into `blocks`,
+        // returning `false` without decoding anything if they're all zero.
+        fn roaring_container_blocks(&self, key: usize, blocks: &mut Vec<u64>) -> bool {
+            let num_sb = self.sb_classes.len() + (self.len > 0) as usize;
+            let start = key * BLOCKS_PER_CONTAINER;
+            let end = num_sb.min(start + BLOCKS_PER_CONTAINER);
+            let classes = &self.sb_classes[start..end.min(self.sb_classes.len())];
+            let has_last_block = end > self.sb_classes.len();
+            if classes.iter().all(|&c| c == 0)
+                && !(has_last_block && self.last_block.num_ones > 0)
+            {
+                return false;
+            }
+            blocks.clear();
+            blocks.extend(self.blocks_from(start).take(end - start));
+            true
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use alloc::vec;
+        use alloc::vec::Vec;
+        use crate::test_helpers::hash_u64;
+        use crate::RsDict;
+        use roaring::{RoaringBitmap, RoaringTreemap};
+
+        // Mix long runs of zeros and ones with sparse and random blocks so we
+        // hit every container type.
+        fn test_blocks(seeds: &[u64]) -> Vec<u64> {
+            let mut blocks = vec![];
+            for &seed in seeds.iter().take(12) {
+                let x = hash_u64(seed);
+                let n = (x % 400) as usize;
+                match seed % 4 {
+                    0 => blocks.extend(std::iter::repeat(0).take(n)),
+                    1 => blocks.extend(std::iter::repeat(!0).take(n)),
+                    2 => blocks.extend((0..n as u64).map(|i| {
+                        let y = hash_u64(x ^ i);
+                        y & (y >> 9) & (y >> 17)
+                    })),
+                    _ => blocks.extend((0..n as u64 / 8).map(|i| hash_u64(x ^ i))),
+                }
+            }
+            blocks
+        }
+
+        #[quickcheck]
+        fn qc_roaring(seeds: Vec<u64>, tail: Vec<bool>) {
+            let mut r = RsDict::from_blocks(test_blocks(&seeds).into_iter());
+            for &bit in &tail {
+                r.push(bit);
+            }
+            // `RoaringBitmap`'s `PartialEq` distinguishes container types, so
+            // compare contents instead.
+            let mut bytes = vec![];
+            r.serialize_roaring_into(&mut bytes).unwrap();
+            let bitmap = RoaringBitmap::deserialize_from(&bytes[..]).unwrap();
+            assert_eq!(bitmap.len(), r.count_ones() as u64);
+            assert!(bitmap.iter().map(|p| p as u64).eq(r.iter()));
+
+            let from_bitmap = RsDict::from(&bitmap);
+            assert_eq!(from_bitmap.count_ones(), r.count_ones());
+            assert!(from_bitmap.iter().eq(r.iter()));
+
+            let treemap = RoaringTreemap::from_sorted_iter(r.iter()).unwrap();
+            assert!(RsDict::from(&treemap).iter().eq(r.iter()));
+        }
+
+        #[test]
+        #[should_panic(expected = "too long")]
+        fn test_treemap_max() {
+            let treemap: RoaringTreemap = [0, u64::MAX].iter().copied().collect();
+            let _ = RsDict::from(&treemap);
+        }
+    }
+}
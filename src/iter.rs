@@ -1,36 +1,47 @@
 use super::*;
-use std::ops::Range;
+use core::ops::Range;
 
-impl<'a> IntoIterator for &'a RsDict {
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> IntoIterator
+    for &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
     type Item = u64;
-    type IntoIter = RsDictIterator<'a>;
+    type IntoIter = RsDictIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>;
 
     fn into_iter(self) -> Self::IntoIter {
         RsDictIterator::new(self)
     }
 }
 
-impl<'a> RsDict {
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
     /// Return an iterator over all the indices of the bits set to one
     /// which are inside the provided range.
-    pub fn iter_in_range(&'a self, range: Range<u64>) -> RsDictIterator<'a> {
+    pub fn iter_in_range(
+        &self,
+        range: Range<u64>,
+    ) -> RsDictIterator<'_, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C> {
         RsDictIterator::new_in_range(self, range)
     }
-}
-impl<'a> RsDict {
+
     /// return an Iterator over the indices of the bits set to one in the RsDict.
-    pub fn iter(&'a self) -> RsDictIterator<'a> {
+    pub fn iter(&self) -> RsDictIterator<'_, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C> {
         self.into_iter()
     }
 }
 
 #[derive(Debug)]
-pub struct RsDictIterator<'a> {
+pub struct RsDictIterator<
+    'a,
+    const LARGE_BLOCK_SIZE: u64 = { DEFAULT_LARGE_BLOCK_SIZE },
+    const SELECT_BLOCK_SIZE: u64 = { DEFAULT_SELECT_BLOCK_SIZE },
+    C: SmallBlock = u64,
+> {
     /// reference to the rsdict which is being iter
     /// this is needed to read and decode the blocks
-    father: &'a RsDict,
+    father: &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>,
     /// The current code already decoded
-    current_code: u64,
+    current_code: C,
     /// Current pointer inside the enum_blocks
     ptr: usize,
     /// Current small_block index
@@ -41,25 +52,35 @@ pub struct RsDictIterator<'a> {
     max: Option<u64>,
 }
 
-impl<'a> RsDictIterator<'a> {
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDictIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
 
     /// Create a structure that iter over all the indices of the bits set to one
     /// which are inside the provided range.
     /// 
     /// This iterator should give the same result of:
     /// ```
-    /// r.iter().filter(|x| range.contains(&x))
+    /// # let r = rsdict::RsDict::from_blocks([0xdead_beef_u64, 0xf00d].iter().copied());
+    /// # let range = 10..80;
+    /// # let expected: Vec<u64> =
+    /// r.iter().filter(|x| range.contains(x))
+    /// # .collect();
+    /// # assert_eq!(r.iter_in_range(range).collect::<Vec<_>>(), expected);
     /// ```
     #[inline]
-    pub fn new_in_range(father: &RsDict, range: Range<u64>) -> RsDictIterator {
+    pub fn new_in_range(
+        father: &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>,
+        range: Range<u64>,
+    ) -> Self {
         let pos = range.start;
 
         // if the start value is bigger than all the rest, return an empty iterator
         // code == 0 and index == max_index ensures no return value
         if pos >= father.len() as u64 {
             return RsDictIterator{
-                father:father,
-                current_code: 0,
+                father,
+                current_code: C::ZERO,
                 ptr: 0,
                 index: 0,
                 max_index: 0, 
@@ -72,13 +93,13 @@ impl<'a> RsDictIterator<'a> {
             // Get the current code
             let mut code = father.last_block.bits;
             // Clear the bits
-            code = clear_lower_bits(code, pos - father.last_block_ind());
+            code = code.clear_below(pos - father.last_block_ind());
             // Return the iterator
             return RsDictIterator{
-                    father:father,
+                    father,
                     current_code: code,
                     ptr: 0, // no need to initialize, it will never be used
-                    index: father.last_block_ind() as usize / SMALL_BLOCK_SIZE as usize,
+                    index: (father.last_block_ind() / C::BITS) as usize,
                     max_index: father.sb_classes.len(),
                     max: Some(range.end),
                 };
@@ -86,34 +107,31 @@ impl<'a> RsDictIterator<'a> {
 
         // Start with the rank from our position's large block.
         let lblock = pos / LARGE_BLOCK_SIZE;
-        let LargeBlock {
-            mut pointer,
-            rank,
-        } = father.large_blocks[lblock as usize];
+        let mut pointer = father.large_blocks[lblock as usize].pointer;
 
         // Add in the ranks (i.e. the classes) per small block up to our
         // position's small block.
-        let sblock_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
-        let sblock = (pos / SMALL_BLOCK_SIZE) as usize;
+        let sblock_start =
+            (lblock * RsDict::<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>::SMALL_BLOCK_PER_LARGE_BLOCK)
+                as usize;
+        let sblock = (pos / C::BITS) as usize;
         // Scan the small blocks from the start of the large block
         // to the current small block to compute the pointer in the enumerative
         // codes array.
-        let (class_sum, length_sum) =
-            rank_acceleration::scan_block(&father.sb_classes, sblock_start, sblock);
-
-            pointer += length_sum;
+        let (_, length_sum) = C::scan_block(father.sb_classes_padded(), sblock_start, sblock);
+        pointer += length_sum;
         // Get the class of the current block
         let sb_class = father.sb_classes[sblock];
-        let enum_code_length = ENUM_CODE_LENGTH[sb_class as usize];
+        let enum_code_length = C::code_length(sb_class);
         // Read the code
         let enum_code = father.read_sb_index(pointer, enum_code_length);
         // decode the code
-        let mut code = enum_code::decode(enum_code, sb_class);
+        let mut code = enum_code.decode(sb_class);
         // filter the lower bits
-        code = clear_lower_bits(code, pos - (sblock as u64 * SMALL_BLOCK_SIZE));
+        code = code.clear_below(pos - (sblock as u64 * C::BITS));
         // Create the iterator
         RsDictIterator{
-            father:father,
+            father,
             current_code: code,
             ptr: (pointer + enum_code_length as u64) as usize,
             index: sblock,
@@ -125,16 +143,16 @@ impl<'a> RsDictIterator<'a> {
     
     /// Create a structure that iter over all the indices of the bits set to one.
     #[inline]
-    pub fn new(father: &RsDict) -> RsDictIterator {
-        if father.sb_classes.len() > 0 {
+    pub fn new(father: &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>) -> Self {
+        if !father.sb_classes.is_empty() {
             let class = father.sb_classes[0];
-            let code_length = ENUM_CODE_LENGTH[class as usize] as usize;
-            let code = father.sb_indices.get(0, code_length);
-            let current_code = enum_code::decode(code, class);
+            let code_length = C::code_length(class);
+            let code = father.read_sb_index(0, code_length);
+            let current_code = code.decode(class);
             RsDictIterator{
-                father:father,
-                current_code: current_code,
-                ptr: code_length,
+                father,
+                current_code,
+                ptr: code_length as usize,
                 index: 0,
                 max_index: father.sb_classes.len(),
                 max: None,
@@ -142,7 +160,7 @@ impl<'a> RsDictIterator<'a> {
         } else {
             // all the data is in the last block
             RsDictIterator{
-                father:father,
+                father,
                 current_code: father.last_block.bits,
                 ptr: 0,
                 index: 0,
@@ -153,18 +171,24 @@ impl<'a> RsDictIterator<'a> {
     }
 }
 
-impl<'a> Iterator for RsDictIterator<'a> {
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> Iterator
+    for RsDictIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
     type Item = u64;
     /// The iteration code takes inspiration from https://lemire.me/blog/2018/02/21/iterating-over-set-bits-quickly/
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        // if we have no values left, then read a new u64 chunk from the Rsdict
-        if self.current_code == 0 { 
+        // if we have no values left, then read a new small block from the Rsdict
+        if self.current_code == C::ZERO {
             // find the next not empty word
             self.current_code = loop {
                 self.index += 1;
                 // if its the last block just dump it
                 if self.index == self.max_index {
+                    // the last block may not have any ones either
+                    if self.father.last_block.bits == C::ZERO {
+                        return None;
+                    }
                     break self.father.last_block.bits;
                 }
                 // if we are over just end the iterator
@@ -178,10 +202,10 @@ impl<'a> Iterator for RsDictIterator<'a> {
                     continue;
                 }
                 // we have ones in the current code so we can decode it
-                let code_length = ENUM_CODE_LENGTH[class as usize] as usize;
-                let enum_code = self.father.sb_indices.get(self.ptr, code_length);
-                self.ptr += code_length;
-                break enum_code::decode(enum_code, class);
+                let code_length = C::code_length(class);
+                let enum_code = self.father.read_sb_index(self.ptr as u64, code_length);
+                self.ptr += code_length as usize;
+                break enum_code.decode(class);
             };
         }
 
@@ -192,10 +216,10 @@ impl<'a> Iterator for RsDictIterator<'a> {
         let t = self.current_code.trailing_zeros();
 
         // clear it from the current code
-        self.current_code = clear_lowest_bit_set(self.current_code);
+        self.current_code = self.current_code.clear_lowest_one();
 
         // compute the result value
-        let result = self.index as u64 * SMALL_BLOCK_SIZE + t as u64;
+        let result = self.index as u64 * C::BITS + t;
 
         // Check if we exceeds the max value
         if let Some(_max) = &self.max {
@@ -207,24 +231,3 @@ impl<'a> Iterator for RsDictIterator<'a> {
         Some(result)
     }
 }
-
-#[inline(always)]
-/// Clear the lowest set bit.
-fn clear_lowest_bit_set(x: u64) -> u64 {
-    // if possible, use the fast instruction
-    #[cfg(target_feature="bmi1")]
-    {
-        return unsafe{core::arch::x86_64::_blsr_u64(x)};
-    }
-    // Otherwise fall down to the generic implementation
-    #[cfg(not(target_feature="bmi1"))]
-    {
-        x & (x - 1)
-    }
-}
-
-#[inline(always)]
-/// Clear the lowest num bits.
-fn clear_lower_bits(code: u64, num:u64) -> u64 {
-    code & u64::MAX.wrapping_shl(num as u32)
-}
\ No newline at end of file
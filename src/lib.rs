@@ -46,6 +46,17 @@
 //! Select works similarly where we start with the large block indices, skip
 //! over as many small blocks as possible, and then select within a small
 //! block. As with rank, we're able to select within a small block directly.
+//!
+//! # `no_std` support
+//! The crate only needs `alloc`.  The default `std` feature detects CPU
+//! features like `popcnt`, `bmi2` and `ssse3` at runtime.  Without it, the
+//! accelerated routines are selected at compile time from the enabled target
+//! features, e.g. with `-C target-cpu=native`.
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", feature = "roaring", test))]
+extern crate std;
 
 #[cfg(test)]
 extern crate quickcheck;
@@ -53,32 +64,107 @@ extern crate quickcheck;
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
-use std::mem;
+use alloc::vec::Vec;
+use core::iter::FromIterator;
+use core::{mem, ptr};
+
+// Whether the CPU supports an x86 target feature, which we can only detect at
+// runtime with `std`.
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+macro_rules! has_x86_feature {
+    ($feature:tt) => {
+        std::is_x86_feature_detected!($feature)
+    };
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+macro_rules! has_x86_feature {
+    ($feature:tt) => {
+        cfg!(target_feature = $feature)
+    };
+}
 
+mod auto;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
 mod constants;
 mod enum_code;
+mod fm_index;
 mod iter;
 mod hash;
-use hash::*;
+mod interleaved;
+#[cfg(any(feature = "bitvec", feature = "fixedbitset", feature = "roaring"))]
+mod interop;
+mod plain;
+mod rank_select;
+mod rle;
+mod serialize;
+mod slice;
+mod space;
+mod split;
+mod validate;
+mod wavelet_matrix;
+mod wide;
 
 mod rank_acceleration;
 
 #[cfg(test)]
 mod test_helpers;
 
-use self::constants::{
-    LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, SMALL_BLOCK_PER_LARGE_BLOCK, SMALL_BLOCK_SIZE,
-};
-use self::enum_code::ENUM_CODE_LENGTH;
+use self::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, SMALL_BLOCK_SIZE};
+
+pub use self::auto::AutoRsDict;
+pub use self::builder::RsDictBuilder;
+pub use self::enum_code::SmallBlock;
+pub use self::fm_index::FmIndex;
+pub use self::interleaved::InterleavedRsDict;
+pub use self::plain::PlainRsDict;
+pub use self::rank_select::{BitAccess, BitIterOnes, BitRank, BitSelect, RankSelect};
+pub use self::rle::RleRsDict;
+pub use self::serialize::FromBytesError;
+pub use self::slice::RsDictSlice;
+pub use self::space::SpaceBreakdown;
+pub use self::validate::ValidationError;
+pub use self::wavelet_matrix::WaveletMatrix;
+pub use self::wide::WideRsDict;
 
 /// Data structure for efficiently computing both rank and select queries
-#[derive(Debug, Clone, PartialEq)]
-pub struct RsDict {
+///
+/// The large block size and select sampling rate (both in bits) can be tuned
+/// with the `LARGE_BLOCK_SIZE` and `SELECT_BLOCK_SIZE` parameters, which default
+/// to 1024 and 4096.  Larger values use less space for the indices at the cost
+/// of scanning more small blocks per query.  The small block type `C` sets how
+/// many bits each enumerative code covers: 64 with the default `u64`, or 128
+/// with `u128`, as in [`WideRsDict`].  `LARGE_BLOCK_SIZE` must be a nonzero
+/// multiple of the small block size, and `SELECT_BLOCK_SIZE` must be greater
+/// than it.
+///
+/// ```
+/// use rsdict::RsDict;
+///
+/// // Use 4096-bit large blocks and sample every 16384th bit for select.
+/// let mut r = RsDict::<4096, 16384>::with_block_sizes();
+/// r.push(true);
+/// r.push(false);
+/// assert_eq!(r.select(0, false), Some(1));
+/// ```
+///
+/// Unsupported block sizes are rejected at compile time:
+/// ```compile_fail
+/// let r = rsdict::RsDict::<1000, 4096>::with_block_sizes();
+/// ```
+#[derive(Debug)]
+pub struct RsDict<
+    const LARGE_BLOCK_SIZE: u64 = { DEFAULT_LARGE_BLOCK_SIZE },
+    const SELECT_BLOCK_SIZE: u64 = { DEFAULT_SELECT_BLOCK_SIZE },
+    C: SmallBlock = u64,
+> {
     len: u64,
     num_ones: u64,
     num_zeros: u64,
 
-    // Small block metadata (stored every SMALL_BLOCK_SIZE bits):
+    // Small block metadata (stored every `C::BITS` bits):
     // * number of set bits (the "class") for the small block
     // * index within a class for each small block; note that the indexes are
     //   variable length (see `ENUM_CODE_LENGTH`), so there isn't direct access
@@ -93,12 +179,62 @@ pub struct RsDict {
 
     // Select acceleration:
     // `select_{one,zero}_inds` store the (offset / LARGE_BLOCK_SIZE) of each
-    // SELECT_BLOCK_SIZE'th bit.
+    // SELECT_BLOCK_SIZE'th bit.  Either index can be turned off with
+    // `RsDictBuilder`, in which case it's left empty and select binary
+    // searches over the large blocks instead.
     select_one_inds: Vec<u64>,
     select_zero_inds: Vec<u64>,
+    select1_index: bool,
+    select0_index: bool,
 
     // Current in-progress small block we're appending to
-    last_block: LastBlock,
+    last_block: LastBlock<C>,
+}
+
+// `Vec::clone` doesn't preserve capacity, so we need to restore the padding at
+// the end of `sb_classes` that `rank_acceleration::scan_block` reads into.
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> Clone
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    fn clone(&self) -> Self {
+        let mut sb_classes = self.sb_classes.clone();
+        Self::reserve_sb_classes_padding(&mut sb_classes, 0);
+        Self {
+            len: self.len,
+            num_ones: self.num_ones,
+            num_zeros: self.num_zeros,
+            sb_classes,
+            sb_indices: self.sb_indices.clone(),
+            large_blocks: self.large_blocks.clone(),
+            select_one_inds: self.select_one_inds.clone(),
+            select_zero_inds: self.select_zero_inds.clone(),
+            select1_index: self.select1_index,
+            select0_index: self.select0_index,
+            last_block: self.last_block.clone(),
+        }
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> Default
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    #[inline]
+    fn default() -> Self {
+        Self::with_block_sizes()
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock> FromIterator<bool>
+    for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    fn from_iter<I: IntoIterator<Item = bool>>(bits: I) -> Self {
+        let (blocks, tail) = pack_bits(bits.into_iter());
+        let mut r = Self::from_blocks_with_block_sizes(blocks.into_iter());
+        for bit in tail {
+            r.push(bit);
+        }
+        r
+    }
 }
 
 impl RsDict {
@@ -106,40 +242,113 @@ impl RsDict {
     /// is equivalent to pushing each bit one at a time but is much faster.
     #[inline]
     pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
-        if is_x86_feature_detected!("popcnt") {
-            unsafe { Self::from_blocks_popcount(blocks) }
-        } else {
-            Self::from_blocks_impl(blocks)
+        Self::from_blocks_with_block_sizes(blocks)
+    }
+
+    /// Create a new `RsDict` with zero capacity.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_block_sizes()
+    }
+
+    /// Create a new `RsDict` with room for `n` bits preallocated, so [`RsDict::capacity`] is at
+    /// least `n`.
+    #[inline]
+    pub fn with_capacity(n: usize) -> Self {
+        Self::with_capacity_and_block_sizes(n)
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    const SMALL_BLOCK_PER_LARGE_BLOCK: u64 = LARGE_BLOCK_SIZE / C::BITS;
+
+    // `rank_acceleration::scan_block` reads a large block's classes in whole
+    // 16-byte vectors, so it may read this many entries from the start of any
+    // large block, past the end of `sb_classes` but not of its allocation.
+    const SB_CLASSES_ALIGN: usize = (Self::SMALL_BLOCK_PER_LARGE_BLOCK as usize + 15) / 16 * 16;
+
+    // Evaluated (and checked at compile time) by every constructor.
+    const VALID_BLOCK_SIZES: () = assert!(
+        LARGE_BLOCK_SIZE > 0
+            && LARGE_BLOCK_SIZE % C::BITS == 0
+            && SELECT_BLOCK_SIZE > C::BITS,
+        "LARGE_BLOCK_SIZE must be a nonzero multiple of the small block size and \
+         SELECT_BLOCK_SIZE must exceed it"
+    );
+
+    /// Same as [`RsDict::from_blocks`] but with custom block sizes.
+    #[inline]
+    pub fn from_blocks_with_block_sizes(blocks: impl Iterator<Item = u64>) -> Self {
+        Self::from_blocks_with_indices(blocks, true, true)
+    }
+
+    #[inline]
+    fn from_blocks_with_indices(
+        blocks: impl Iterator<Item = u64>,
+        select1_index: bool,
+        select0_index: bool,
+    ) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_BLOCK_SIZES;
+        #[cfg(target_arch = "x86_64")]
+        {
+            if has_x86_feature!("popcnt") {
+                return unsafe {
+                    Self::from_blocks_popcount(blocks, select1_index, select0_index)
+                };
+            }
         }
+        Self::from_blocks_impl(blocks, select1_index, select0_index)
     }
 
+    #[cfg(target_arch = "x86_64")]
     #[target_feature(enable = "popcnt")]
     #[inline]
-    unsafe fn from_blocks_popcount(blocks: impl Iterator<Item = u64>) -> Self {
-        Self::from_blocks_impl(blocks)
+    unsafe fn from_blocks_popcount(
+        blocks: impl Iterator<Item = u64>,
+        select1_index: bool,
+        select0_index: bool,
+    ) -> Self {
+        Self::from_blocks_impl(blocks, select1_index, select0_index)
     }
 
     #[inline(always)]
-    fn from_blocks_impl(blocks: impl Iterator<Item = u64>) -> Self {
+    fn from_blocks_impl(
+        blocks: impl Iterator<Item = u64>,
+        select1_index: bool,
+        select0_index: bool,
+    ) -> Self {
+        // The hint counts blocks, so preallocate everything but the codes,
+        // which are usually much shorter than the blocks they encode.
         let (_, hint) = blocks.size_hint();
-        let hint = hint.unwrap_or(0);
+        let hint_bits = hint.unwrap_or(0).saturating_mul(64);
 
-        let mut large_blocks = Vec::with_capacity(hint / LARGE_BLOCK_SIZE as usize);
-        let mut select_one_inds = Vec::with_capacity(hint / SELECT_BLOCK_SIZE as usize);
-        let mut select_zero_inds = Vec::with_capacity(hint / SELECT_BLOCK_SIZE as usize);
-        let mut sb_classes = Vec::with_capacity(hint / SMALL_BLOCK_SIZE as usize);
-        let mut sb_indices = VarintBuffer::with_capacity(hint);
+        let mut large_blocks = Vec::with_capacity(div_ceil(hint_bits, LARGE_BLOCK_SIZE as usize));
+        let index_capacity = |enabled: bool| {
+            if enabled {
+                div_ceil(hint_bits, SELECT_BLOCK_SIZE as usize)
+            } else {
+                0
+            }
+        };
+        let mut select_one_inds = Vec::with_capacity(index_capacity(select1_index));
+        let mut select_zero_inds = Vec::with_capacity(index_capacity(select0_index));
+        let num_sb = div_ceil(hint_bits, C::BITS as usize);
+        let mut sb_classes = Vec::with_capacity(Self::sb_classes_padded_len(num_sb));
+        let mut sb_indices = VarintBuffer::with_capacity(0);
         let mut last_block = LastBlock::new();
 
         let mut num_ones = 0;
         let mut num_zeros = 0;
 
-        let mut iter = blocks.enumerate().peekable();
+        let mut iter = small_blocks::<C>(blocks).enumerate().peekable();
 
-        while let Some((i, block)) = iter.next() {
-            let sb_class = block.count_ones() as u8;
+        while let Some((i, (block, block_len))) = iter.next() {
+            let sb_class = block.ones() as u8;
 
-            if i as u64 % SMALL_BLOCK_PER_LARGE_BLOCK == 0 {
+            if i as u64 % Self::SMALL_BLOCK_PER_LARGE_BLOCK == 0 {
                 let lblock = LargeBlock {
                     rank: num_ones,
                     pointer: sb_indices.len() as u64,
@@ -152,14 +361,13 @@ impl RsDict {
             if iter.peek().is_none() {
                 last_block.bits = block;
                 last_block.num_ones = sb_class as u64;
-                last_block.num_zeros = 64 - sb_class as u64;
+                last_block.num_zeros = block_len - sb_class as u64;
             } else {
                 sb_classes.push(sb_class);
-                let (code_len, code) = enum_code::encode(block, sb_class);
-                sb_indices.push(code_len as usize, code);
+                sb_indices.push_code(C::code_length(sb_class), block.encode(sb_class));
             }
 
-            let lb_start = i as u64 * SMALL_BLOCK_SIZE / LARGE_BLOCK_SIZE;
+            let lb_start = i as u64 * C::BITS / LARGE_BLOCK_SIZE;
 
             // We want to see if there's any j in [num_ones, num_ones + sb_class) such
             // that j % SELECT_BLOCK_SIZE = 0.  We can do this arithmetically by
@@ -181,29 +389,29 @@ impl RsDict {
             // to both numerators so we don't ever underflow when subtracting one.
             let start = num_ones + SELECT_BLOCK_SIZE - 1;
             let end = num_ones + SELECT_BLOCK_SIZE + sb_class as u64 - 1;
-            if start / SELECT_BLOCK_SIZE != end / SELECT_BLOCK_SIZE {
+            if select1_index && start / SELECT_BLOCK_SIZE != end / SELECT_BLOCK_SIZE {
                 select_one_inds.push(lb_start);
             }
 
             // Now do the same for the zero indices.
             let start = num_zeros + SELECT_BLOCK_SIZE - 1;
-            let end = num_zeros + SELECT_BLOCK_SIZE + (64 - sb_class as u64) - 1;
-            if start / SELECT_BLOCK_SIZE != end / SELECT_BLOCK_SIZE {
+            let end = num_zeros + SELECT_BLOCK_SIZE + (block_len - sb_class as u64) - 1;
+            if select0_index && start / SELECT_BLOCK_SIZE != end / SELECT_BLOCK_SIZE {
                 select_zero_inds.push(lb_start);
             }
 
             num_ones += sb_class as u64;
-            num_zeros += 64 - sb_class as u64;
+            num_zeros += block_len - sb_class as u64;
         }
 
-        let num_sb = sb_classes.len();
-        let align = SMALL_BLOCK_PER_LARGE_BLOCK as usize;
-        sb_classes.reserve((num_sb + align - 1) / align * align);
+        Self::reserve_sb_classes_padding(&mut sb_classes, 0);
 
         Self {
             large_blocks,
             select_one_inds,
             select_zero_inds,
+            select1_index,
+            select0_index,
             sb_classes,
             sb_indices,
 
@@ -215,30 +423,159 @@ impl RsDict {
         }
     }
 
-    /// Create a new `RsDict` with zero capacity.
+    // Number of entries `sb_classes` needs allocated when it holds `num_sb`
+    // classes, so `scan_block` can read `SB_CLASSES_ALIGN` entries from the
+    // start of the last large block.
     #[inline]
-    pub fn new() -> Self {
-        Self::with_capacity(0)
+    fn sb_classes_padded_len(num_sb: usize) -> usize {
+        if num_sb == 0 {
+            return 0;
+        }
+        let sb_per_lb = Self::SMALL_BLOCK_PER_LARGE_BLOCK as usize;
+        (num_sb - 1) / sb_per_lb * sb_per_lb + Self::SB_CLASSES_ALIGN
     }
 
-    /// Create a new `RsDict` with the given capacity preallocated.
+    // Reserve room for `additional` more classes along with their padding,
+    // and zero everything past the end.  `scan_block` masks off the entries
+    // past the end of its range, but it still loads them, so they have to be
+    // initialized.  Call this after anything that might reallocate or shrink
+    // `sb_classes`, since neither keeps the spare capacity's contents.
     #[inline]
-    pub fn with_capacity(n: usize) -> Self {
-        Self {
-            large_blocks: Vec::with_capacity(n / LARGE_BLOCK_SIZE as usize),
-            select_one_inds: Vec::with_capacity(n / SELECT_BLOCK_SIZE as usize),
-            select_zero_inds: Vec::with_capacity(n / SELECT_BLOCK_SIZE as usize),
-            sb_classes: Vec::with_capacity(n / SMALL_BLOCK_SIZE as usize),
-            sb_indices: VarintBuffer::with_capacity(n),
+    fn reserve_sb_classes_padding(sb_classes: &mut Vec<u8>, additional: usize) {
+        let len = sb_classes.len();
+        let padded_len = Self::sb_classes_padded_len(len + additional);
+        sb_classes.reserve(padded_len - len);
+        // SAFETY: We just reserved room for `padded_len` entries.
+        unsafe { ptr::write_bytes(sb_classes.as_mut_ptr().add(len), 0, padded_len - len) };
+    }
+
+    // `sb_classes` along with its zeroed padding, which is what
+    // `rank_acceleration::scan_block` needs to read from.
+    #[inline]
+    fn sb_classes_padded(&self) -> &[u8] {
+        let padded_len = Self::sb_classes_padded_len(self.sb_classes.len());
+        // SAFETY: `reserve_sb_classes_padding` keeps the padding allocated and
+        // initialized.
+        unsafe { core::slice::from_raw_parts(self.sb_classes.as_ptr(), padded_len) }
+    }
+
+    /// Same as [`RsDict::new`] but with custom block sizes.
+    #[inline]
+    pub fn with_block_sizes() -> Self {
+        Self::with_capacity_and_block_sizes(0)
+    }
+
+    /// Same as [`RsDict::with_capacity`] but with custom block sizes.
+    #[inline]
+    pub fn with_capacity_and_block_sizes(n: usize) -> Self {
+        Self::with_capacity_and_indices(n, true, true)
+    }
+
+    #[inline]
+    fn with_capacity_and_indices(n: usize, select1_index: bool, select0_index: bool) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_BLOCK_SIZES;
+        let mut r = Self {
+            large_blocks: Vec::new(),
+            select_one_inds: Vec::new(),
+            select_zero_inds: Vec::new(),
+            select1_index,
+            select0_index,
+            sb_classes: Vec::new(),
+            sb_indices: VarintBuffer::with_capacity(0),
 
             len: 0,
             num_ones: 0,
             num_zeros: 0,
 
             last_block: LastBlock::new(),
+        };
+        r.reserve(n);
+        r
+    }
+
+    /// Return the number of bits the dictionary can hold without reallocating its small block
+    /// classes or its indices.  The enumerative codes aren't counted, since their length depends
+    /// on the bits pushed, and their buffer grows as needed.
+    pub fn capacity(&self) -> usize {
+        let sb_per_lb = Self::SMALL_BLOCK_PER_LARGE_BLOCK as usize;
+        let classes_capacity = self.sb_classes.capacity();
+        // The small blocks with room for their classes and padding, plus the
+        // last block, which doesn't have a class yet.
+        let class_blocks = if classes_capacity < Self::SB_CLASSES_ALIGN {
+            1
+        } else {
+            ((classes_capacity - Self::SB_CLASSES_ALIGN) / sb_per_lb + 1) * sb_per_lb + 1
+        };
+        // Every new bit might be the one that needs a new select sample.
+        let index_bits = |inds: &Vec<u64>, enabled: bool, count: u64| {
+            if enabled {
+                let samples = inds.capacity().saturating_mul(SELECT_BLOCK_SIZE as usize);
+                (self.len as usize).saturating_add(samples - count as usize)
+            } else {
+                usize::MAX
+            }
+        };
+        class_blocks
+            .saturating_mul(C::BITS as usize)
+            .min(
+                self.large_blocks
+                    .capacity()
+                    .saturating_mul(LARGE_BLOCK_SIZE as usize),
+            )
+            .min(index_bits(&self.select_one_inds, self.select1_index, self.num_ones))
+            .min(index_bits(&self.select_zero_inds, self.select0_index, self.num_zeros))
+    }
+
+    /// Reserve room for pushing at least `additional_bits` more bits without reallocating, so
+    /// [`RsDict::capacity`] is at least `len() + additional_bits` afterwards.  Room for the new
+    /// bits' codes is reserved assuming they compress as well as the bits already in the
+    /// dictionary, so nothing is reserved for them in an empty dictionary.
+    pub fn reserve(&mut self, additional_bits: usize) {
+        let bits = (self.len as usize).saturating_add(additional_bits);
+        let num_sb = div_ceil(bits, C::BITS as usize).saturating_sub(1);
+        let reserve_to = |len: usize, target: usize| target.saturating_sub(len);
+
+        let classes = reserve_to(self.sb_classes.len(), num_sb);
+        Self::reserve_sb_classes_padding(&mut self.sb_classes, classes);
+        if !self.sb_classes.is_empty() {
+            // Assume the new small blocks have the same average code length.
+            let code_bits = self.sb_indices.len().saturating_mul(classes) / self.sb_classes.len();
+            let target = div_ceil(self.sb_indices.len().saturating_add(code_bits), 64);
+            self.sb_indices
+                .buf
+                .reserve(reserve_to(self.sb_indices.buf.len(), target));
+        }
+        let num_lblocks = div_ceil(bits, LARGE_BLOCK_SIZE as usize);
+        self.large_blocks
+            .reserve(reserve_to(self.large_blocks.len(), num_lblocks));
+        let num_select = |count: u64| {
+            div_ceil((count as usize).saturating_add(additional_bits), SELECT_BLOCK_SIZE as usize)
+        };
+        if self.select1_index {
+            let target = num_select(self.num_ones);
+            self.select_one_inds
+                .reserve(reserve_to(self.select_one_inds.len(), target));
+        }
+        if self.select0_index {
+            let target = num_select(self.num_zeros);
+            self.select_zero_inds
+                .reserve(reserve_to(self.select_zero_inds.len(), target));
         }
     }
 
+    /// Release as much unused memory as possible, keeping only the padding that rank's
+    /// vectorized scan over the small block classes reads into.
+    pub fn shrink_to_fit(&mut self) {
+        let padded_len = Self::sb_classes_padded_len(self.sb_classes.len());
+        self.sb_classes.shrink_to(padded_len);
+        Self::reserve_sb_classes_padding(&mut self.sb_classes, 0);
+        self.sb_indices.buf.shrink_to_fit();
+        self.large_blocks.shrink_to_fit();
+        self.select_one_inds.shrink_to_fit();
+        self.select_zero_inds.shrink_to_fit();
+    }
+
     /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
     /// out-of-bounds.
     #[inline]
@@ -249,7 +586,7 @@ impl RsDict {
         // If we're in the last block, count the number of ones set after our
         // bit in the last block and remove that from the global count.
         if self.is_last_block(pos) {
-            let trailing_ones = self.last_block.count_suffix(pos % SMALL_BLOCK_SIZE);
+            let trailing_ones = self.last_block.count_suffix(pos % C::BITS);
             return rank_by_bit(self.num_ones - trailing_ones, pos, bit);
         }
 
@@ -262,23 +599,77 @@ impl RsDict {
 
         // Add in the ranks (i.e. the classes) per small block up to our
         // position's small block.
-        let sblock_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
-        let sblock = (pos / SMALL_BLOCK_SIZE) as usize;
+        let sblock_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sblock = (pos / C::BITS) as usize;
         let (class_sum, length_sum) =
-            rank_acceleration::scan_block(&self.sb_classes, sblock_start, sblock);
+            C::scan_block(self.sb_classes_padded(), sblock_start, sblock);
         rank += class_sum;
         pointer += length_sum;
 
         // If we aren't on a small block boundary, add in the rank within the small block.
-        if pos % SMALL_BLOCK_SIZE != 0 {
+        if pos % C::BITS != 0 {
             let sb_class = self.sb_classes[sblock];
-            let code = self.read_sb_index(pointer, ENUM_CODE_LENGTH[sb_class as usize]);
-            rank += enum_code::rank(code, sb_class, pos % SMALL_BLOCK_SIZE);
+            let code = self.read_sb_index(pointer, C::code_length(sb_class));
+            rank += code.rank(sb_class, pos % C::BITS);
         }
 
         rank_by_bit(rank, pos, bit)
     }
 
+    /// Hint that `rank(pos, _)` will be called soon, prefetching the large block and small
+    /// block classes it reads first.  Out-of-bounds positions are ignored.
+    #[inline]
+    pub fn prefetch_rank(&self, pos: u64) {
+        if pos >= self.len || self.is_last_block(pos) {
+            return;
+        }
+        let lblock = pos / LARGE_BLOCK_SIZE;
+        rank_acceleration::prefetch(&self.large_blocks, lblock as usize);
+        let sblock_start = lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK;
+        rank_acceleration::prefetch(&self.sb_classes, sblock_start as usize);
+    }
+
+    // Second stage of prefetching for `rank(pos, _)`: Assuming the large block
+    // and classes are (or will shortly be) in cache, prefetch the code.
+    #[inline]
+    fn prefetch_rank_code(&self, pos: u64) {
+        if pos >= self.len || self.is_last_block(pos) || pos % C::BITS == 0 {
+            return;
+        }
+        let lblock = pos / LARGE_BLOCK_SIZE;
+        let sblock_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sblock = (pos / C::BITS) as usize;
+        let (_, length_sum) = C::scan_block(self.sb_classes_padded(), sblock_start, sblock);
+        let pointer = self.large_blocks[lblock as usize].pointer + length_sum;
+        rank_acceleration::prefetch(&self.sb_indices.buf, (pointer / 64) as usize);
+    }
+
+    /// Compute `rank(pos, bit)` for each of `positions`.  For random positions over a large
+    /// dictionary, this is faster than calling `rank` in a loop, since it prefetches the memory
+    /// for upcoming queries while resolving earlier ones.  Panics if any position is
+    /// out-of-bounds.
+    pub fn rank_many(&self, positions: &[u64], bit: bool) -> Vec<u64> {
+        // How many queries ahead to start each prefetching stage.  The first
+        // stage must finish before the second stage can read the large block.
+        const CODE_DISTANCE: usize = 8;
+        const BLOCK_DISTANCE: usize = 2 * CODE_DISTANCE;
+
+        let mut ranks = Vec::with_capacity(positions.len());
+        for &pos in positions.iter().take(BLOCK_DISTANCE) {
+            self.prefetch_rank(pos);
+        }
+        for (i, &pos) in positions.iter().enumerate() {
+            if let Some(&ahead) = positions.get(i + BLOCK_DISTANCE) {
+                self.prefetch_rank(ahead);
+            }
+            if let Some(&ahead) = positions.get(i + CODE_DISTANCE) {
+                self.prefetch_rank_code(ahead);
+            }
+            ranks.push(self.rank(pos, bit));
+        }
+        ranks
+    }
+
     /// Query the `pos`th bit (zero-indexed) of the underlying bit and the number of set bits to the
     /// left of `pos` in a single operation.  This method is faster than calling `get_bit(pos)` and
     /// `rank(pos, true)` separately.
@@ -288,28 +679,28 @@ impl RsDict {
             panic!("Out of bounds position: {} >= {}", pos, self.len);
         }
         if self.is_last_block(pos) {
-            let sb_pos = pos % SMALL_BLOCK_SIZE;
+            let sb_pos = pos % C::BITS;
             let bit = self.last_block.get_bit(sb_pos);
             let after_rank = self.last_block.count_suffix(sb_pos);
             return (bit, self.num_ones - after_rank);
         }
         let lblock = pos / LARGE_BLOCK_SIZE;
-        let sblock = (pos / SMALL_BLOCK_SIZE) as usize;
-        let sblock_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sblock = (pos / C::BITS) as usize;
+        let sblock_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
         let LargeBlock {
             mut pointer,
             mut rank,
         } = self.large_blocks[lblock as usize];
         for &sb_class in &self.sb_classes[sblock_start..sblock] {
-            pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+            pointer += C::code_length(sb_class) as u64;
             rank += sb_class as u64;
         }
         let sb_class = self.sb_classes[sblock];
-        let code_length = ENUM_CODE_LENGTH[sb_class as usize];
+        let code_length = C::code_length(sb_class);
         let code = self.read_sb_index(pointer, code_length);
 
-        rank += enum_code::rank(code, sb_class, pos % SMALL_BLOCK_SIZE);
-        let bit = enum_code::decode_bit(code, sb_class, pos % SMALL_BLOCK_SIZE);
+        rank += code.rank(sb_class, pos % C::BITS);
+        let bit = code.decode_bit(sb_class, pos % C::BITS);
         (bit, rank)
     }
 
@@ -344,40 +735,44 @@ impl RsDict {
 
         // Our rank must be in the last block.
         if rank >= prefix_num_zeros {
-            let lb_rank = (rank - prefix_num_zeros) as u8;
+            let lb_rank = rank - prefix_num_zeros;
             return Some(self.last_block_ind() + self.last_block.select0(lb_rank));
         }
 
         // First, use the select pointer to jump forward to a large block and
         // then walk forward over the large blocks until we pass our rank.
-        let select_ind = (rank / SELECT_BLOCK_SIZE) as usize;
-        let lb_start = self.select_zero_inds[select_ind] as usize;
-        let mut lblock = None;
-        for (i, large_block) in self.large_blocks[lb_start..].iter().enumerate() {
-            let lb_ix = (lb_start + i) as u64;
-            let lb_rank = lb_ix * LARGE_BLOCK_SIZE - large_block.rank;
-            if rank < lb_rank {
-                lblock = Some(lb_ix - 1);
-                break;
+        let lblock = if self.select0_index {
+            let select_ind = (rank / SELECT_BLOCK_SIZE) as usize;
+            let lb_start = self.select_zero_inds[select_ind] as usize;
+            let mut lblock = None;
+            for (i, large_block) in self.large_blocks[lb_start..].iter().enumerate() {
+                let lb_ix = (lb_start + i) as u64;
+                let lb_rank = lb_ix * LARGE_BLOCK_SIZE - large_block.rank;
+                if rank < lb_rank {
+                    lblock = Some(lb_ix - 1);
+                    break;
+                }
             }
-        }
-        let lblock = lblock.unwrap_or(self.large_blocks.len() as u64 - 1);
+            lblock.unwrap_or(self.large_blocks.len() as u64 - 1)
+        } else {
+            self.search_large_blocks(rank, false)
+        };
         let large_block = &self.large_blocks[lblock as usize];
 
         // Next, iterate over the small blocks, using their cached class to
         // subtract out our rank.
-        let sb_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sb_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
         let mut pointer = large_block.pointer;
         let mut remaining = rank - (lblock * LARGE_BLOCK_SIZE - large_block.rank);
         for (i, &sb_class) in self.sb_classes[sb_start..].iter().enumerate() {
-            let sb_zeros = (SMALL_BLOCK_SIZE as u8 - sb_class) as u64;
-            let code_length = ENUM_CODE_LENGTH[sb_class as usize];
+            let sb_zeros = C::BITS - sb_class as u64;
+            let code_length = C::code_length(sb_class);
 
             // Our desired rank is within this block.
             if remaining < sb_zeros {
                 let code = self.read_sb_index(pointer, code_length);
-                let sb_rank = (sb_start + i) as u64 * SMALL_BLOCK_SIZE;
-                let block_rank = enum_code::select0(code, sb_class, remaining);
+                let sb_rank = (sb_start + i) as u64 * C::BITS;
+                let block_rank = code.select0(sb_class, remaining);
                 return Some(sb_rank + block_rank);
             }
 
@@ -397,33 +792,37 @@ impl RsDict {
 
         let prefix_num_ones = self.num_ones - self.last_block.num_ones;
         if rank >= prefix_num_ones {
-            let lb_rank = (rank - prefix_num_ones) as u8;
+            let lb_rank = rank - prefix_num_ones;
             return Some(self.last_block_ind() + self.last_block.select1(lb_rank));
         }
 
-        let select_ind = (rank / SELECT_BLOCK_SIZE) as usize;
-        let lb_start = self.select_one_inds[select_ind] as usize;
-        let mut lblock = None;
-        for (i, large_block) in self.large_blocks[lb_start..].iter().enumerate() {
-            if rank < large_block.rank {
-                lblock = Some((lb_start + i - 1) as u64);
-                break;
+        let lblock = if self.select1_index {
+            let select_ind = (rank / SELECT_BLOCK_SIZE) as usize;
+            let lb_start = self.select_one_inds[select_ind] as usize;
+            let mut lblock = None;
+            for (i, large_block) in self.large_blocks[lb_start..].iter().enumerate() {
+                if rank < large_block.rank {
+                    lblock = Some((lb_start + i - 1) as u64);
+                    break;
+                }
             }
-        }
-        let lblock = lblock.unwrap_or(self.large_blocks.len() as u64 - 1);
+            lblock.unwrap_or(self.large_blocks.len() as u64 - 1)
+        } else {
+            self.search_large_blocks(rank, true)
+        };
         let large_block = &self.large_blocks[lblock as usize];
 
-        let sb_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sb_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
         let mut pointer = large_block.pointer;
         let mut remaining = rank - large_block.rank;
         for (i, &sb_class) in self.sb_classes[sb_start..].iter().enumerate() {
             let sb_ones = sb_class as u64;
-            let code_length = ENUM_CODE_LENGTH[sb_class as usize];
+            let code_length = C::code_length(sb_class);
 
             if remaining < sb_ones {
                 let code = self.read_sb_index(pointer, code_length);
-                let sb_rank = (sb_start + i) as u64 * SMALL_BLOCK_SIZE;
-                let block_rank = enum_code::select1(code, sb_class, remaining);
+                let sb_rank = (sb_start + i) as u64 * C::BITS;
+                let block_rank = code.select1(sb_class, remaining);
                 return Some(sb_rank + block_rank);
             }
 
@@ -460,18 +859,18 @@ impl RsDict {
     /// Push a bit at the end of the underlying bitmap.
     #[inline]
     pub fn push(&mut self, bit: bool) {
-        if self.len % SMALL_BLOCK_SIZE == 0 {
+        if self.len % C::BITS == 0 {
             self.write_block();
         }
         if bit {
-            self.last_block.set_one(self.len % SMALL_BLOCK_SIZE);
-            if self.num_ones % SELECT_BLOCK_SIZE == 0 {
+            self.last_block.set_one(self.len % C::BITS);
+            if self.select1_index && self.num_ones % SELECT_BLOCK_SIZE == 0 {
                 self.select_one_inds.push(self.len / LARGE_BLOCK_SIZE);
             }
             self.num_ones += 1;
         } else {
-            self.last_block.set_zero(self.len % SMALL_BLOCK_SIZE);
-            if self.num_zeros % SELECT_BLOCK_SIZE == 0 {
+            self.last_block.set_zero(self.len % C::BITS);
+            if self.select0_index && self.num_zeros % SELECT_BLOCK_SIZE == 0 {
                 self.select_zero_inds.push(self.len / LARGE_BLOCK_SIZE);
             }
             self.num_zeros += 1;
@@ -483,19 +882,19 @@ impl RsDict {
     #[inline]
     pub fn get_bit(&self, pos: u64) -> bool {
         if self.is_last_block(pos) {
-            return self.last_block.get_bit(pos % SMALL_BLOCK_SIZE);
+            return self.last_block.get_bit(pos % C::BITS);
         }
         let lblock = pos / LARGE_BLOCK_SIZE;
-        let sblock = (pos / SMALL_BLOCK_SIZE) as usize;
-        let sblock_start = (lblock * SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let sblock = (pos / C::BITS) as usize;
+        let sblock_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
         let mut pointer = self.large_blocks[lblock as usize].pointer;
         for &sb_class in &self.sb_classes[sblock_start..sblock] {
-            pointer += ENUM_CODE_LENGTH[sb_class as usize] as u64;
+            pointer += C::code_length(sb_class) as u64;
         }
         let sb_class = self.sb_classes[sblock];
-        let code_length = ENUM_CODE_LENGTH[sb_class as usize];
+        let code_length = C::code_length(sb_class);
         let code = self.read_sb_index(pointer, code_length);
-        enum_code::decode_bit(code, sb_class, pos % SMALL_BLOCK_SIZE)
+        code.decode_bit(sb_class, pos % C::BITS)
     }
 
     #[inline]
@@ -506,17 +905,14 @@ impl RsDict {
             let sb_class = block.num_ones as u8;
             self.sb_classes.push(sb_class);
 
-            // To avoid indexing past the end of our allocation when
-            // scanning through a large block, reserve some extra space to
-            // ensure that we always have a full large block in
-            // `sb_classes`.
-            let num_sb = self.sb_classes.len();
-            let align = SMALL_BLOCK_PER_LARGE_BLOCK as usize;
-            self.sb_classes
-                .reserve((num_sb + align - 1) / align * align);
+            // To avoid reading past the end of our allocation (or into
+            // uninitialized memory) when scanning through a large block,
+            // make sure the padding after the last large block's classes is
+            // still allocated and zeroed.
+            Self::reserve_sb_classes_padding(&mut self.sb_classes, 0);
 
-            let (code_len, code) = enum_code::encode(block.bits, sb_class);
-            self.sb_indices.push(code_len as usize, code);
+            let code = block.bits.encode(sb_class);
+            self.sb_indices.push_code(C::code_length(sb_class), code);
         }
         if self.len % LARGE_BLOCK_SIZE == 0 {
             let lblock = LargeBlock {
@@ -527,12 +923,47 @@ impl RsDict {
         }
     }
 
+    // Binary search for the last large block with at most `rank` instances of
+    // `bit` before it, for when we don't have a select index.
+    #[inline]
+    fn search_large_blocks(&self, rank: u64, bit: bool) -> u64 {
+        let before = |lblock: usize| {
+            let large_block = &self.large_blocks[lblock];
+            rank_by_bit(large_block.rank, lblock as u64 * LARGE_BLOCK_SIZE, bit)
+        };
+        // Invariant: `before(lo) <= rank` and `before(hi) > rank`, where we
+        // treat `hi == len` as past the end.
+        let (mut lo, mut hi) = (0, self.large_blocks.len());
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if before(mid) <= rank {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo as u64
+    }
+
+    // Position in `sb_indices` of the `sblock`th small block's code, where
+    // `sblock` may be one past the last encoded block.
+    #[inline]
+    fn sb_pointer(&self, sblock: usize) -> u64 {
+        if sblock == 0 {
+            return 0;
+        }
+        let lblock = sblock as u64 / Self::SMALL_BLOCK_PER_LARGE_BLOCK;
+        let sblock_start = (lblock * Self::SMALL_BLOCK_PER_LARGE_BLOCK) as usize;
+        let (_, length_sum) = C::scan_block(self.sb_classes_padded(), sblock_start, sblock);
+        self.large_blocks[lblock as usize].pointer + length_sum
+    }
+
     #[inline]
     fn last_block_ind(&self) -> u64 {
         if self.len == 0 {
             return 0;
         }
-        ((self.len - 1) / SMALL_BLOCK_SIZE) * SMALL_BLOCK_SIZE
+        ((self.len - 1) / C::BITS) * C::BITS
     }
 
     #[inline]
@@ -541,8 +972,35 @@ impl RsDict {
     }
 
     #[inline]
-    fn read_sb_index(&self, ptr: u64, code_len: u8) -> u64 {
-        self.sb_indices.get(ptr as usize, code_len as usize)
+    fn read_sb_index(&self, ptr: u64, code_len: u8) -> C {
+        self.sb_indices.get_code(ptr as usize, code_len)
+    }
+
+    // Decode the bitmap into 64-bit blocks, where the last small block is
+    // padded with zeros.
+    #[inline]
+    fn blocks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks_from(0)
+    }
+
+    // Same as `blocks`, but starting with the `sblock`th small block.
+    fn blocks_from(&self, sblock: usize) -> impl Iterator<Item = u64> + '_ {
+        let mut pointer = self.sb_pointer(sblock);
+        let last_block = if self.len > 0 {
+            Some(self.last_block.bits)
+        } else {
+            None
+        };
+        self.sb_classes[sblock..]
+            .iter()
+            .map(move |&sb_class| {
+                let code_length = C::code_length(sb_class);
+                let code = self.read_sb_index(pointer, code_length);
+                pointer += code_length as u64;
+                code.decode(sb_class)
+            })
+            .chain(last_block)
+            .flat_map(|small_block| (0..C::WORDS).map(move |i| small_block.word(i)))
     }
 }
 
@@ -602,56 +1060,90 @@ impl VarintBuffer {
         ret & mask
     }
 
+    // Push a small block's `code_len`-bit code, a word at a time.
+    #[inline]
+    fn push_code<C: SmallBlock>(&mut self, code_len: u8, code: C) {
+        for i in 0..C::WORDS {
+            let num_bits = (code_len as usize).saturating_sub(64 * i).min(64);
+            self.push(num_bits, code.word(i));
+        }
+    }
+
+    // Read a `code_len`-bit code starting at `index`, a word at a time.
+    #[inline]
+    fn get_code<C: SmallBlock>(&self, index: usize, code_len: u8) -> C {
+        let mut code = C::ZERO;
+        for i in 0..C::WORDS {
+            let num_bits = (code_len as usize).saturating_sub(64 * i).min(64);
+            code = code.with_word(i, self.get(index + 64 * i, num_bits));
+        }
+        code
+    }
+
     #[inline]
     fn len(&self) -> usize {
         self.len
     }
+
+    // Shorten the buffer to its first `len` bits, clearing the bits after
+    // them like `push` expects.
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.buf.truncate(div_ceil(len, 64));
+        if len % 64 != 0 {
+            self.buf[len / 64] &= (1 << (len % 64)) - 1;
+        }
+        self.len = len;
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
-struct LastBlock {
-    bits: u64,
+struct LastBlock<C = u64> {
+    bits: C,
     num_ones: u64,
     num_zeros: u64,
 }
 
-impl LastBlock {
+impl<C: SmallBlock> LastBlock<C> {
     #[inline]
     fn new() -> Self {
         LastBlock {
-            bits: 0,
+            bits: C::ZERO,
             num_ones: 0,
             num_zeros: 0,
         }
     }
 
     #[inline]
-    fn select0(&self, rank: u8) -> u64 {
-        debug_assert!(rank < self.num_zeros as u8);
-        enum_code::select1_raw(!self.bits, rank as u64)
+    fn select0(&self, rank: u64) -> u64 {
+        debug_assert!(rank < self.num_zeros);
+        self.bits.select0_raw(rank)
     }
 
     #[inline]
-    fn select1(&self, rank: u8) -> u64 {
-        debug_assert!(rank < self.num_ones as u8);
-        enum_code::select1_raw(self.bits, rank as u64)
+    fn select1(&self, rank: u64) -> u64 {
+        debug_assert!(rank < self.num_ones);
+        self.bits.select1_raw(rank)
     }
 
     #[inline]
     // Count the number of bits set at indices i >= pos
     fn count_suffix(&self, pos: u64) -> u64 {
-        (self.bits >> pos).count_ones() as u64
+        self.bits.count_suffix(pos)
     }
 
     #[inline]
     fn get_bit(&self, pos: u64) -> bool {
-        (self.bits >> pos) & 1 == 1
+        self.bits.get_bit(pos)
     }
 
     #[inline]
     // Only call one of `set_one` or `set_zeros` for any `pos`.
     fn set_one(&mut self, pos: u64) {
-        self.bits |= 1 << pos;
+        self.bits = self.bits.set_bit(pos);
         self.num_ones += 1;
     }
 
@@ -661,6 +1153,66 @@ impl LastBlock {
     }
 }
 
+// Group 64-bit blocks into small blocks, low block first, along with the
+// number of bits in each.  Only the last small block can be short.
+#[inline]
+fn small_blocks<C: SmallBlock>(
+    blocks: impl Iterator<Item = u64>,
+) -> impl Iterator<Item = (C, u64)> {
+    let mut blocks = blocks.fuse();
+    core::iter::from_fn(move || {
+        let mut small_block = C::ZERO.with_word(0, blocks.next()?);
+        let mut num_bits = 64;
+        for i in 1..C::WORDS {
+            match blocks.next() {
+                Some(block) => small_block = small_block.with_word(i, block),
+                None => break,
+            }
+            num_bits += 64;
+        }
+        Some((small_block, num_bits))
+    })
+}
+
+// Build a dictionary from `len` bits stored in 64-bit blocks, where the bits
+// past `len` are ignored.
+fn from_padded_blocks<C: SmallBlock>(
+    mut blocks: impl Iterator<Item = u64>,
+    len: usize,
+) -> RsDict<DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, C> {
+    let mut r = RsDict::from_blocks_with_block_sizes(blocks.by_ref().take(len / 64));
+    if len % 64 != 0 {
+        let last_block = blocks.next().expect("Too few blocks for length");
+        for i in 0..len % 64 {
+            r.push((last_block >> i) & 1 != 0);
+        }
+    }
+    r
+}
+
+#[inline]
+fn div_ceil(x: usize, y: usize) -> usize {
+    x / y + (x % y != 0) as usize
+}
+
+// Pack `bits` into 64-bit blocks, returning them along with the leftover bits
+// that don't fill a whole block.
+fn pack_bits(bits: impl Iterator<Item = bool>) -> (Vec<u64>, Vec<bool>) {
+    let mut blocks = Vec::with_capacity(bits.size_hint().0 / 64);
+    let mut tail = Vec::with_capacity(64);
+    for bit in bits {
+        tail.push(bit);
+        if tail.len() == 64 {
+            let block = tail
+                .drain(..)
+                .enumerate()
+                .fold(0u64, |block, (i, bit)| block | (bit as u64) << i);
+            blocks.push(block);
+        }
+    }
+    (blocks, tail)
+}
+
 #[inline]
 fn rank_by_bit(x: u64, n: u64, b: bool) -> u64 {
     if b {
@@ -672,14 +1224,15 @@ fn rank_by_bit(x: u64, n: u64, b: bool) -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use super::RsDict;
-    use crate::test_helpers::hash_u64;
+    use alloc::vec::Vec;
+    use super::{div_ceil, RsDict, RsDictBuilder};
+    use crate::test_helpers::{check_rank_select, hash_u64};
 
     // Ask quickcheck to generate blocks of 64 bits so we get test
     // coverage for ranges spanning multiple small blocks.
     fn test_rsdict(blocks: Vec<u64>) -> (Vec<bool>, RsDict) {
         let mut bits = Vec::with_capacity(blocks.len() * 64);
-        let to_pop = blocks.get(0).unwrap_or(&0) % 64;
+        let to_pop = blocks.first().unwrap_or(&0) % 64;
         for block in blocks {
             for i in 0..4 {
                 let block = hash_u64(block.wrapping_add(i));
@@ -714,8 +1267,8 @@ mod tests {
             block
         });
         let mut block_rs_dict = RsDict::from_blocks(blocks);
-        for i in (bits.len() / 64 * 64)..bits.len() {
-            block_rs_dict.push(bits[i]);
+        for &bit in &bits[(bits.len() / 64 * 64)..] {
+            block_rs_dict.push(bit);
         }
 
         assert_eq!(rs_dict.len, block_rs_dict.len);
@@ -775,6 +1328,65 @@ mod tests {
         }
     }
 
+    #[quickcheck]
+    fn qc_rank_many(blocks: Vec<u64>, seeds: Vec<u64>) {
+        let (bits, rs_dict) = test_rsdict(blocks);
+        if bits.is_empty() {
+            return;
+        }
+        let positions: Vec<u64> = seeds
+            .iter()
+            .map(|&s| hash_u64(s) % bits.len() as u64)
+            .collect();
+        for &bit in &[false, true] {
+            let expected: Vec<u64> = positions.iter().map(|&p| rs_dict.rank(p, bit)).collect();
+            assert_eq!(rs_dict.rank_many(&positions, bit), expected);
+        }
+    }
+
+    #[quickcheck]
+    fn qc_select_without_index(blocks: Vec<u64>) {
+        let (bits, _) = test_rsdict(blocks);
+        let full_blocks: Vec<u64> = bits
+            .chunks_exact(64)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |block, (i, &bit)| block | ((bit as u64) << i))
+            })
+            .collect();
+        let tail = &bits[full_blocks.len() * 64..];
+
+        // Use small blocks so we cover multiple large blocks and select samples.
+        let mut full = RsDict::<128, 256>::from_blocks_with_block_sizes(full_blocks.iter().cloned());
+        let mut from_blocks = RsDictBuilder::<128, 256>::with_block_sizes()
+            .select0_index(false)
+            .from_blocks(full_blocks.iter().cloned());
+        let mut pushed = RsDictBuilder::<128, 256>::with_block_sizes()
+            .select1_index(false)
+            .build();
+        for &bit in &bits[..full_blocks.len() * 64] {
+            pushed.push(bit);
+        }
+        for &bit in tail {
+            full.push(bit);
+            from_blocks.push(bit);
+            pushed.push(bit);
+        }
+        assert!(from_blocks.select_zero_inds.is_empty());
+        assert!(pushed.select_one_inds.is_empty());
+
+        for r in 0..=full.count_ones() as u64 {
+            assert_eq!(from_blocks.select1(r), full.select1(r));
+            assert_eq!(pushed.select1(r), full.select1(r));
+        }
+        for r in 0..=full.count_zeros() as u64 {
+            assert_eq!(from_blocks.select0(r), full.select0(r));
+            assert_eq!(pushed.select0(r), full.select0(r));
+        }
+    }
+
     #[quickcheck]
     fn qc_get_bit(blocks: Vec<u64>) {
         let (bits, rs_dict) = test_rsdict(blocks);
@@ -795,4 +1407,99 @@ mod tests {
             }
         }
     }
+
+    fn check_capacity<const L: u64, const S: u64>(bits: &[bool]) {
+        let (head, tail) = bits.split_at(bits.len() / 2);
+        let mut rs_dict = RsDict::<L, S>::with_block_sizes();
+        for &bit in head {
+            rs_dict.push(bit);
+            assert!(rs_dict.capacity() >= rs_dict.len());
+        }
+        rs_dict.reserve(tail.len());
+        assert!(rs_dict.capacity() >= bits.len());
+        // Only the codes may need more room than we reserved.
+        let index_size = |r: &RsDict<L, S>| r.heap_size_bytes() - r.sb_indices.buf.capacity() * 8;
+        let heap_size = index_size(&rs_dict);
+        for &bit in tail {
+            rs_dict.push(bit);
+        }
+        assert_eq!(index_size(&rs_dict), heap_size);
+        let heap_size = rs_dict.heap_size_bytes();
+
+        let expected = rs_dict.clone();
+        rs_dict.shrink_to_fit();
+        assert!(rs_dict.heap_size_bytes() <= heap_size);
+        assert!(rs_dict.capacity() >= rs_dict.len());
+        let padded_len = RsDict::<L, S>::sb_classes_padded_len(rs_dict.sb_classes.len());
+        assert!(rs_dict.sb_classes.capacity() >= padded_len);
+        assert_eq!(rs_dict, expected);
+        for i in 0..bits.len() as u64 {
+            assert_eq!(rs_dict.rank(i, true), expected.rank(i, true));
+        }
+        for r in 0..=rs_dict.count_ones() as u64 {
+            assert_eq!(rs_dict.select1(r), expected.select1(r));
+        }
+    }
+
+    #[test]
+    fn test_with_capacity_sparse() {
+        // One bit in 64 set takes 6 bits of code per small block, so the
+        // dictionary should stay well under the size of the bitmap.
+        let n = 1 << 16;
+        let mut rs_dict = RsDict::with_capacity(n);
+        for i in 0..n {
+            rs_dict.push(i % 64 == 0);
+        }
+        assert!(rs_dict.capacity() >= n);
+        assert!(rs_dict.heap_size_bytes() < n / 8 / 2);
+
+        rs_dict.shrink_to_fit();
+        let code_bytes = div_ceil(rs_dict.sb_indices.len(), 64) * 8;
+        assert_eq!(rs_dict.sb_indices.buf.capacity() * 8, code_bytes);
+        assert!(rs_dict.heap_size_bytes() < n / 8 / 2);
+
+        // Once there are blocks to go by, `reserve` sizes the codes like them.
+        rs_dict.reserve(n);
+        let code_capacity = rs_dict.sb_indices.buf.capacity() * 64;
+        assert!(code_capacity >= 2 * rs_dict.sb_indices.len());
+        assert!(code_capacity < 3 * rs_dict.sb_indices.len());
+    }
+
+    #[quickcheck]
+    fn qc_capacity(blocks: Vec<u64>) {
+        let (bits, _) = test_rsdict(blocks);
+        check_capacity::<64, 128>(&bits);
+        check_capacity::<1024, 4096>(&bits);
+        check_capacity::<1536, 4096>(&bits);
+    }
+
+    fn check_block_sizes<const L: u64, const S: u64>(bits: &[bool]) {
+        let mut rs_dict = RsDict::<L, S>::with_block_sizes();
+        for &bit in bits {
+            rs_dict.push(bit);
+        }
+        let blocks = bits.chunks_exact(64).map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u64, |block, (j, &bit)| block | ((bit as u64) << j))
+        });
+        let mut block_rs_dict = RsDict::<L, S>::from_blocks_with_block_sizes(blocks);
+        for &bit in &bits[(bits.len() / 64 * 64)..] {
+            block_rs_dict.push(bit);
+        }
+        assert_eq!(rs_dict, block_rs_dict);
+
+        check_rank_select!(&rs_dict, bits);
+        assert_eq!(rs_dict.iter().count(), rs_dict.count_ones());
+    }
+
+    #[quickcheck]
+    fn qc_block_sizes(blocks: Vec<u64>) {
+        let (bits, _) = test_rsdict(blocks);
+        check_block_sizes::<64, 128>(&bits);
+        check_block_sizes::<512, 1024>(&bits);
+        check_block_sizes::<1536, 4096>(&bits);
+        check_block_sizes::<4096, 16384>(&bits);
+    }
 }
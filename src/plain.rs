@@ -0,0 +1,336 @@
+//! Uncompressed variant of `RsDict` for dense, random bitmaps.
+//!
+//! When about half the bits in each small block are set, the enumerative code
+//! for the block is (nearly) as long as the block itself, so `RsDict` spends
+//! time decoding without saving any space.  `PlainRsDict` stores the raw
+//! 64-bit words instead, along with a rank per large block and the same
+//! select sampling scheme as `RsDict`.
+use alloc::vec::Vec;
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, SMALL_BLOCK_SIZE};
+use super::enum_code;
+use super::{pack_bits, rank_by_bit};
+use core::iter::FromIterator;
+
+const LARGE_BLOCK_SIZE: u64 = DEFAULT_LARGE_BLOCK_SIZE;
+const SELECT_BLOCK_SIZE: u64 = DEFAULT_SELECT_BLOCK_SIZE;
+const WORDS_PER_LARGE_BLOCK: usize = (LARGE_BLOCK_SIZE / SMALL_BLOCK_SIZE) as usize;
+
+/// Rank and select dictionary over an uncompressed bitmap.
+///
+/// ```
+/// use rsdict::PlainRsDict;
+///
+/// let mut r = PlainRsDict::from_blocks(vec![0b1011, 0b110].into_iter());
+/// r.push(true);
+/// assert_eq!(r.len(), 129);
+/// assert_eq!(r.rank(66, true), 4);
+/// assert_eq!(r.select1(4), Some(66));
+/// assert_eq!(r.select0(1), Some(4));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlainRsDict {
+    len: u64,
+    num_ones: u64,
+    num_zeros: u64,
+
+    // The bitmap, where bits past `len` in the last word are always zero.
+    words: Vec<u64>,
+    // Number of ones before each large block.
+    large_blocks: Vec<u64>,
+
+    // Same as `RsDict`: the large block of every `SELECT_BLOCK_SIZE`th one
+    // and zero.
+    select_one_inds: Vec<u64>,
+    select_zero_inds: Vec<u64>,
+}
+
+impl Default for PlainRsDict {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<bool> for PlainRsDict {
+    fn from_iter<I: IntoIterator<Item = bool>>(bits: I) -> Self {
+        let (blocks, tail) = pack_bits(bits.into_iter());
+        let mut r = Self::from_blocks(blocks.into_iter());
+        for bit in tail {
+            r.push(bit);
+        }
+        r
+    }
+}
+
+impl PlainRsDict {
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit blocks.
+    pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
+        let (_, hint) = blocks.size_hint();
+        let mut r = Self::with_capacity(hint.unwrap_or(0) * 64);
+        for block in blocks {
+            if r.len % LARGE_BLOCK_SIZE == 0 {
+                r.large_blocks.push(r.num_ones);
+            }
+            let ones = block.count_ones() as u64;
+            let zeros = SMALL_BLOCK_SIZE - ones;
+            let lblock = r.len / LARGE_BLOCK_SIZE;
+
+            // See `RsDict::from_blocks` for why this finds whether a multiple
+            // of `SELECT_BLOCK_SIZE` falls in `[num_ones, num_ones + ones)`.
+            let start = r.num_ones + SELECT_BLOCK_SIZE - 1;
+            if start / SELECT_BLOCK_SIZE != (start + ones) / SELECT_BLOCK_SIZE {
+                r.select_one_inds.push(lblock);
+            }
+            let start = r.num_zeros + SELECT_BLOCK_SIZE - 1;
+            if start / SELECT_BLOCK_SIZE != (start + zeros) / SELECT_BLOCK_SIZE {
+                r.select_zero_inds.push(lblock);
+            }
+
+            r.words.push(block);
+            r.num_ones += ones;
+            r.num_zeros += zeros;
+            r.len += SMALL_BLOCK_SIZE;
+        }
+        r
+    }
+
+    /// Create a new `PlainRsDict` with zero capacity.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Create a new `PlainRsDict` with the given capacity preallocated.
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            len: 0,
+            num_ones: 0,
+            num_zeros: 0,
+            words: Vec::with_capacity((n + 63) / 64),
+            large_blocks: Vec::with_capacity(n / LARGE_BLOCK_SIZE as usize + 1),
+            select_one_inds: Vec::with_capacity(n / SELECT_BLOCK_SIZE as usize),
+            select_zero_inds: Vec::with_capacity(n / SELECT_BLOCK_SIZE as usize),
+        }
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    #[inline]
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let lblock = (pos / LARGE_BLOCK_SIZE) as usize;
+        let word = (pos / SMALL_BLOCK_SIZE) as usize;
+        let mut rank = self.large_blocks[lblock];
+        for &w in &self.words[lblock * WORDS_PER_LARGE_BLOCK..word] {
+            rank += w.count_ones() as u64;
+        }
+        let mask = (1 << (pos % SMALL_BLOCK_SIZE)) - 1;
+        rank += (self.words[word] & mask).count_ones() as u64;
+        rank_by_bit(rank, pos, bit)
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    #[inline]
+    pub fn get_bit(&self, pos: u64) -> bool {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let word = self.words[(pos / SMALL_BLOCK_SIZE) as usize];
+        (word >> (pos % SMALL_BLOCK_SIZE)) & 1 != 0
+    }
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    #[inline]
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        if bit {
+            self.select1(rank)
+        } else {
+            self.select0(rank)
+        }
+    }
+
+    /// Specialized version of [`PlainRsDict::select`] for finding positions of zeros.
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        if rank >= self.num_zeros {
+            return None;
+        }
+        let zeros_before =
+            |lblock: usize| lblock as u64 * LARGE_BLOCK_SIZE - self.large_blocks[lblock];
+        let lb_start = self.select_zero_inds[(rank / SELECT_BLOCK_SIZE) as usize] as usize;
+        let lblock = (lb_start..self.large_blocks.len())
+            .find(|&lblock| rank < zeros_before(lblock))
+            .map(|lblock| lblock - 1)
+            .unwrap_or(self.large_blocks.len() - 1);
+
+        // Since `rank < num_zeros`, we'll stop before reaching the padding
+        // zeros at the end of the last word.
+        let mut remaining = rank - zeros_before(lblock);
+        let word_start = lblock * WORDS_PER_LARGE_BLOCK;
+        for (i, &word) in self.words[word_start..].iter().enumerate() {
+            let zeros = word.count_zeros() as u64;
+            if remaining < zeros {
+                let word_rank = (word_start + i) as u64 * SMALL_BLOCK_SIZE;
+                return Some(word_rank + enum_code::select1_raw(!word, remaining));
+            }
+            remaining -= zeros;
+        }
+        panic!("Ran out of words when iterating over rank");
+    }
+
+    /// Specialized version of [`PlainRsDict::select`] for finding positions of ones.
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        if rank >= self.num_ones {
+            return None;
+        }
+        let lb_start = self.select_one_inds[(rank / SELECT_BLOCK_SIZE) as usize] as usize;
+        let lblock = self.large_blocks[lb_start..]
+            .iter()
+            .position(|&ones_before| rank < ones_before)
+            .map(|i| lb_start + i - 1)
+            .unwrap_or(self.large_blocks.len() - 1);
+
+        let mut remaining = rank - self.large_blocks[lblock];
+        let word_start = lblock * WORDS_PER_LARGE_BLOCK;
+        for (i, &word) in self.words[word_start..].iter().enumerate() {
+            let ones = word.count_ones() as u64;
+            if remaining < ones {
+                let word_rank = (word_start + i) as u64 * SMALL_BLOCK_SIZE;
+                return Some(word_rank + enum_code::select1_raw(word, remaining));
+            }
+            remaining -= ones;
+        }
+        panic!("Ran out of words when iterating over rank");
+    }
+
+    /// Return the length of the underlying bitmap.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the underlying bitmap is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count the number of set bits in the underlying bitmap.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.num_ones as usize
+    }
+
+    /// Count the number of unset bits in the underlying bitmap.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        self.num_zeros as usize
+    }
+
+    /// Return an iterator over the positions of the ones in the underlying bitmap.
+    #[inline]
+    pub fn iter(&self) -> PlainIterOnes<'_> {
+        PlainIterOnes {
+            words: &self.words,
+            index: 0,
+            current: self.words.first().cloned().unwrap_or(0),
+        }
+    }
+
+    /// Push a bit at the end of the underlying bitmap.
+    #[inline]
+    pub fn push(&mut self, bit: bool) {
+        if self.len % SMALL_BLOCK_SIZE == 0 {
+            self.words.push(0);
+        }
+        if self.len % LARGE_BLOCK_SIZE == 0 {
+            self.large_blocks.push(self.num_ones);
+        }
+        if bit {
+            *self.words.last_mut().unwrap() |= 1 << (self.len % SMALL_BLOCK_SIZE);
+            if self.num_ones % SELECT_BLOCK_SIZE == 0 {
+                self.select_one_inds.push(self.len / LARGE_BLOCK_SIZE);
+            }
+            self.num_ones += 1;
+        } else {
+            if self.num_zeros % SELECT_BLOCK_SIZE == 0 {
+                self.select_zero_inds.push(self.len / LARGE_BLOCK_SIZE);
+            }
+            self.num_zeros += 1;
+        }
+        self.len += 1;
+    }
+}
+
+/// Iterator over the positions of the ones in a `PlainRsDict`.
+#[derive(Debug, Clone)]
+pub struct PlainIterOnes<'a> {
+    words: &'a [u64],
+    // Index of the current word, whose ones we haven't returned yet are left
+    // in `current`.
+    index: usize,
+    current: u64,
+}
+
+impl<'a> Iterator for PlainIterOnes<'a> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        while self.current == 0 {
+            self.index += 1;
+            self.current = *self.words.get(self.index)?;
+        }
+        let bit = self.current.trailing_zeros() as u64;
+        self.current &= self.current - 1;
+        Some(self.index as u64 * SMALL_BLOCK_SIZE + bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::PlainRsDict;
+    use crate::test_helpers::check_rank_select;
+    use crate::RsDict;
+
+    #[quickcheck]
+    fn qc_plain(blocks: Vec<u64>, tail: Vec<bool>) {
+        let mut r = RsDict::from_blocks(blocks.iter().cloned());
+        let mut plain = PlainRsDict::from_blocks(blocks.into_iter());
+        for &bit in &tail {
+            r.push(bit);
+            plain.push(bit);
+        }
+        assert_eq!(plain.len(), r.len());
+        assert_eq!(plain.count_ones(), r.count_ones());
+        assert_eq!(
+            plain.iter().collect::<Vec<_>>(),
+            r.iter().collect::<Vec<_>>()
+        );
+
+        let bits: Vec<bool> = (0..r.len() as u64).map(|i| r.get_bit(i)).collect();
+        assert_eq!(bits.into_iter().collect::<PlainRsDict>(), plain);
+
+        for i in 0..r.len() as u64 {
+            assert_eq!(plain.get_bit(i), r.get_bit(i));
+            assert_eq!(plain.rank(i, true), r.rank(i, true));
+            assert_eq!(plain.rank(i, false), r.rank(i, false));
+        }
+        for i in 0..=r.count_ones() as u64 {
+            assert_eq!(plain.select1(i), r.select1(i));
+        }
+        for i in 0..=r.count_zeros() as u64 {
+            assert_eq!(plain.select0(i), r.select0(i));
+        }
+    }
+
+    #[quickcheck]
+    fn qc_plain_push(bits: Vec<bool>) {
+        let mut plain = PlainRsDict::new();
+        for &bit in &bits {
+            plain.push(bit);
+        }
+        check_rank_select!(&plain, &bits);
+    }
+}
@@ -1,5 +1,12 @@
 use crate::enum_code::ENUM_CODE_LENGTH;
 
+#[cfg_attr(
+    any(
+        all(target_arch = "aarch64", target_feature = "neon"),
+        all(target_arch = "wasm32", target_feature = "simd128")
+    ),
+    allow(dead_code)
+)]
 #[inline]
 fn scan_block_naive(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
     let mut class_sum = 0;
@@ -11,127 +18,243 @@ fn scan_block_naive(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
     (class_sum, length_sum)
 }
 
-#[cfg(not(all(feature = "simd", target_arch="x86_64")))]
+// Hint that we'll soon read `slice[ix]`, ignoring out-of-bounds indices.  This
+// is a no-op on architectures without a stable prefetch intrinsic.
+#[inline]
+pub fn prefetch<T>(slice: &[T], ix: usize) {
+    if ix < slice.len() {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            _mm_prefetch(slice.as_ptr().add(ix) as *const i8, _MM_HINT_T0);
+        }
+    }
+}
+
+// Scan a prefix of a large block of small block classes, returning the
+// sum of the classes and their total encoded length.
+//
+// Preconditions:
+// * start <= end
+// * `classes` must extend to `end` rounded up to a multiple of 16 entries
+//   past `start`, since the vectorized scans load whole 16-byte chunks and
+//   then mask off the entries past `end`.
+//
+// Returns:
+// * class_sum: classes[start..end].sum()
+// * length_sum: classes[start.end].map(|i| ENUM_CODE_LENGTH[i]).sum()
+#[cfg(target_arch = "x86_64")]
+#[inline]
+pub fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+    if has_x86_feature!("ssse3") {
+        unsafe { accelerated::scan_block_ssse3(classes, start, end) }
+    } else {
+        scan_block_naive(classes, start, end)
+    }
+}
+
+// NEON is part of the baseline for AArch64, so there's nothing to detect at
+// runtime.
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+#[inline]
+pub fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+    unsafe { neon::scan_block_neon(classes, start, end) }
+}
+
+// WebAssembly has no runtime feature detection, so SIMD128 has to be enabled
+// at compile time with `-C target-feature=+simd128`.
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline]
+pub fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+    unsafe { simd128::scan_block_simd128(classes, start, end) }
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    all(target_arch = "aarch64", target_feature = "neon"),
+    all(target_arch = "wasm32", target_feature = "simd128")
+)))]
 #[inline]
 pub fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
     scan_block_naive(classes, start, end)
 }
 
-#[cfg(all(feature = "simd", target_arch="x86_64"))]
+#[cfg(target_arch = "x86_64")]
 mod accelerated {
-    use super::scan_block_naive;
     use crate::enum_code::ENUM_CODE_LENGTH;
-    use packed_simd::{u64x2, u8x16, FromBits, IntoBits};
-    use std::arch::x86_64::{__m128i, _mm_sad_epu8};
-    use std::slice;
-    use std::u64;
+    use core::arch::x86_64::*;
 
-    // Scan a prefix of a large block of small block classes, returning the
-    // sum of the classes and their total encoded length.
+    // We want to be able to pack the `ENUM_CODE_LENGTH` table of 65 entries
+    // into a single 16 byte vector.  We can do this with two insights:
     //
-    // Preconditions:
-    // * start <= end <= start + 16
-    // * classes[start] must be 128-bit aligned
+    // 1) The table is symmetric, so we only need to store half of it if we can
+    //    transform the indices.
+    // 2) The table "caps" out at 64 for most of the range in the middle, which
+    //    is the length of the 15th element.  If we just truncate indices greater
+    //    than 15 (after reflection), we'll not change the value.
     //
-    // Returns:
-    // * class_sum: classes[start..end].sum()
-    // * length_sum: classes[start.end].map(|i| ENUM_CODE_LENGTH[i]).sum()
+    // Putting this together, we have f(i) = min(i, 64 - i, 15) such that
+    //
+    //    ENUM_CODE_LENGTH[i] == ENUM_CODE_LENGTH[f(i)] for i in [0, 64].
+    //
+    // Then, `pshufb` can index into the packed table for every class in
+    // parallel.
+    #[target_feature(enable = "ssse3")]
     #[inline]
-    pub fn scan_block(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
-        if is_x86_feature_detected!("ssse3") {
-            unsafe { scan_block_ssse3(classes, start, end) }
-        } else {
-            scan_block_naive(classes, start, end)
-        }
+    unsafe fn code_length_table() -> __m128i {
+        _mm_loadu_si128(ENUM_CODE_LENGTH.as_ptr() as *const __m128i)
     }
 
     #[target_feature(enable = "ssse3")]
     #[inline]
-    unsafe fn scan_block_ssse3(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
-        // Step 1: Load the classes into a u8x16.  Our approach here is to do a
-        // single load and then mask off the elements past `len`.  This is unsafe
-        // since we're potentially reading past the end of the slice, but we're
-        // masking off the extraneous elements before processing them.
-        let len = end - start;
-        debug_assert!(len <= 16);
-
-        // Step 1a: Start with all bits on, shift to turn off the lowest 8n bits,
-        // and then negate to have the lowest 8n bits on.
-        let lo_shift = len as u32 * 8;
-        let lo_mask = !u64::MAX.checked_shl(lo_shift).unwrap_or(0);
-        // Step 1b: Do the same for the remaining 8 bytes.
-        let hi_shift = len.saturating_sub(8) as u32 * 8;
-        let hi_mask = !u64::MAX.checked_shl(hi_shift).unwrap_or(0);
-        let ix_mask = u8x16::from_bits(u64x2::new(lo_mask, hi_mask));
-
-        let classes = {
-            let start = classes.as_ptr().offset(start as isize);
-            let block = slice::from_raw_parts(start, 16);
-
-            // This does bounds and alignment checks in debug builds.
-            let block = u8x16::from_slice_aligned_unchecked(block);
-            block & ix_mask
-        };
-
-        // Step 2: We want to be able to pack the `ENUM_CODE_LENGTH` table of 65
-        // entries into a single u8x16 vector.  We can do this with two insights:
-        //
-        // 1) The table is symmetric, so we only need to store half of it if we can
-        //    transform the indices.
-        // 2) The table "caps" out at 64 for most of the range in the middle, which
-        //    is the length of the 15th element.  If we just truncate indices greater
-        //    than 15 (after reflection), we'll not change the value.
-        //
-        // Putting this together, we have f(i) = min(i, 64 - i, 15) such that
-        //
-        //    ENUM_CODE_LENGTH[i] == ENUM_CODE_LENGTH[f(i)] for i in [0, 64].
-        //
-        let indices = classes
-            .min(u8x16::splat(64) - classes)
-            .min(u8x16::splat(15));
-        const ENUM_CODE_VECTOR: u8x16 = u8x16::new(
-            ENUM_CODE_LENGTH[0],
-            ENUM_CODE_LENGTH[1],
-            ENUM_CODE_LENGTH[2],
-            ENUM_CODE_LENGTH[3],
-            ENUM_CODE_LENGTH[4],
-            ENUM_CODE_LENGTH[5],
-            ENUM_CODE_LENGTH[6],
-            ENUM_CODE_LENGTH[7],
-            ENUM_CODE_LENGTH[8],
-            ENUM_CODE_LENGTH[9],
-            ENUM_CODE_LENGTH[10],
-            ENUM_CODE_LENGTH[11],
-            ENUM_CODE_LENGTH[12],
-            ENUM_CODE_LENGTH[13],
-            ENUM_CODE_LENGTH[14],
-            ENUM_CODE_LENGTH[15],
-        );
-
-        // Step 3: This is the real magic.  Now that we've packed our table into
-        // a vector and transformed our classes into indices into this packed vector,
-        // we can use `pshufb` to index into our table in parallel.
-        let code_lengths = ENUM_CODE_VECTOR.shuffle1_dyn(indices);
-
-        // Step 4: Compute our sums and return.
-        let class_sum = sum_u8x16(classes);
-        let length_sum = sum_u8x16(code_lengths);
+    pub unsafe fn scan_block_ssse3(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+        debug_assert!(start + (end - start + 15) / 16 * 16 <= classes.len());
+        let table = code_length_table();
+        let iota = _mm_setr_epi8(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+        let (mut class_sum, mut length_sum) = (0, 0);
+        let mut chunk_start = start;
+        while chunk_start < end {
+            // Do a single unaligned load and then mask off the elements past
+            // `end`, which are still within `classes`.
+            let len = (end - chunk_start).min(16);
+            let ptr = classes.as_ptr().add(chunk_start) as *const __m128i;
+            let mask = _mm_cmpgt_epi8(_mm_set1_epi8(len as i8), iota);
+            let block = _mm_and_si128(_mm_loadu_si128(ptr), mask);
+
+            let reflected = _mm_min_epu8(block, _mm_sub_epi8(_mm_set1_epi8(64), block));
+            let indices = _mm_min_epu8(reflected, _mm_set1_epi8(15));
+            let code_lengths = _mm_shuffle_epi8(table, indices);
+
+            class_sum += sum_u8x16(block);
+            length_sum += sum_u8x16(code_lengths);
+            chunk_start += 16;
+        }
+        (class_sum, length_sum)
+    }
+
+    // `psadbw` against zero sums each half of a vector of bytes into a u64.
+    #[target_feature(enable = "sse2")]
+    #[inline]
+    unsafe fn sum_u8x16(xs: __m128i) -> u64 {
+        let sums = _mm_sad_epu8(xs, _mm_setzero_si128());
+        let total = _mm_add_epi64(sums, _mm_unpackhi_epi64(sums, sums));
+        _mm_cvtsi128_si64(total) as u64
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod neon {
+    use crate::enum_code::ENUM_CODE_LENGTH;
+    use core::arch::aarch64::*;
+
+    const IOTA: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    // Same approach as `accelerated::scan_block_ssse3`, with `tbl` doing the
+    // packed table lookup and `uaddlv` the horizontal sums.
+    #[inline]
+    pub unsafe fn scan_block_neon(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+        debug_assert!(start + (end - start + 15) / 16 * 16 <= classes.len());
+        let table = vld1q_u8(ENUM_CODE_LENGTH.as_ptr());
+        let iota = vld1q_u8(IOTA.as_ptr());
+
+        let (mut class_sum, mut length_sum) = (0, 0);
+        let mut chunk_start = start;
+        while chunk_start < end {
+            let len = (end - chunk_start).min(16);
+            let mask = vcltq_u8(iota, vdupq_n_u8(len as u8));
+            let block = vandq_u8(vld1q_u8(classes.as_ptr().add(chunk_start)), mask);
+
+            let reflected = vminq_u8(block, vsubq_u8(vdupq_n_u8(64), block));
+            let indices = vminq_u8(reflected, vdupq_n_u8(15));
+            let code_lengths = vqtbl1q_u8(table, indices);
 
+            class_sum += vaddlvq_u8(block) as u64;
+            length_sum += vaddlvq_u8(code_lengths) as u64;
+            chunk_start += 16;
+        }
         (class_sum, length_sum)
     }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod simd128 {
+    use crate::enum_code::ENUM_CODE_LENGTH;
+    use core::arch::wasm32::*;
+
+    // Same approach as `accelerated::scan_block_ssse3`, with `i8x16_swizzle`
+    // doing the packed table lookup.  There's no `psadbw`, so the horizontal
+    // sums widen pairwise up to 32-bit lanes instead.
+    #[inline]
+    pub unsafe fn scan_block_simd128(classes: &[u8], start: usize, end: usize) -> (u64, u64) {
+        debug_assert!(start + (end - start + 15) / 16 * 16 <= classes.len());
+        let table = v128_load(ENUM_CODE_LENGTH.as_ptr() as *const v128);
+        let iota = u8x16(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+        let (mut class_sum, mut length_sum) = (0, 0);
+        let mut chunk_start = start;
+        while chunk_start < end {
+            let len = (end - chunk_start).min(16);
+            let ptr = classes.as_ptr().add(chunk_start) as *const v128;
+            let mask = u8x16_lt(iota, u8x16_splat(len as u8));
+            let block = v128_and(v128_load(ptr), mask);
 
+            let reflected = u8x16_min(block, u8x16_sub(u8x16_splat(64), block));
+            let indices = u8x16_min(reflected, u8x16_splat(15));
+            let code_lengths = i8x16_swizzle(table, indices);
 
+            class_sum += sum_u8x16(block);
+            length_sum += sum_u8x16(code_lengths);
+            chunk_start += 16;
+        }
+        (class_sum, length_sum)
+    }
 
-    // Unfortunately, `packed_simd` doesn't support `psadbw` yet, which is a
-    // great way to sum a u8x16 into a u64x2 in a single SSE2 instruction
     #[inline]
-    unsafe fn sum_u8x16(xs: u8x16) -> u64 {
-        let zero_m128: __m128i = u8x16::splat(0).into_bits();
-        let xs_m128: __m128i = xs.into_bits();
-        let sum_m128 = _mm_sad_epu8(zero_m128, xs_m128);
-        u64x2::from_bits(sum_m128).wrapping_sum()
+    fn sum_u8x16(xs: v128) -> u64 {
+        let sums = u32x4_extadd_pairwise_u16x8(u16x8_extadd_pairwise_u8x16(xs));
+        (u32x4_extract_lane::<0>(sums)
+            + u32x4_extract_lane::<1>(sums)
+            + u32x4_extract_lane::<2>(sums)
+            + u32x4_extract_lane::<3>(sums)) as u64
     }
 }
 
-#[cfg(all(feature = "simd", target_arch="x86_64"))]
-pub use self::accelerated::scan_block;
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::{scan_block, scan_block_naive};
+
+    #[quickcheck]
+    fn qc_scan_block(classes: Vec<u8>, start: usize, len: u8) {
+        let num_classes = classes.len();
+        // Pad with garbage that the scans have to mask off.
+        let classes: Vec<u8> = classes
+            .into_iter()
+            .map(|c| c % 65)
+            .chain(core::iter::repeat(64).take(16))
+            .collect();
+        let start = start % (num_classes + 1);
+        let end = (start + len as usize).min(num_classes);
+        let expected = scan_block_naive(&classes, start, end);
+        assert_eq!(scan_block(&classes, start, end), expected);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            use super::accelerated::scan_block_ssse3;
+            if has_x86_feature!("ssse3") {
+                assert_eq!(unsafe { scan_block_ssse3(&classes, start, end) }, expected);
+            }
+        }
+        #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+        {
+            use super::neon::scan_block_neon;
+            assert_eq!(unsafe { scan_block_neon(&classes, start, end) }, expected);
+        }
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        {
+            use super::simd128::scan_block_simd128;
+            assert_eq!(unsafe { scan_block_simd128(&classes, start, end) }, expected);
+        }
+    }
+}
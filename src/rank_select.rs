@@ -0,0 +1,297 @@
+//! Traits for writing code that's generic over the bitmap representation.
+//!
+//! Each query family gets its own trait so that structures only ask for what
+//! they use, e.g. `WaveletMatrix` just needs [`BitRank`].  Every dictionary
+//! in the crate implements [`BitAccess`], [`BitRank`], [`BitSelect`] and
+//! [`BitIterOnes`].
+use super::auto::AutoIterOnes;
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE};
+use super::interleaved::InterleavedIterOnes;
+use super::iter::RsDictIterator;
+use super::plain::PlainIterOnes;
+use super::rle::RleIterOnes;
+use super::slice::RsDictSliceIterator;
+use super::{
+    AutoRsDict, InterleavedRsDict, PlainRsDict, RleRsDict, RsDict, RsDictSlice, SmallBlock,
+    WideRsDict,
+};
+
+/// Random access to the bits of a bitmap.
+pub trait BitAccess {
+    /// Return the length of the underlying bitmap.
+    fn len(&self) -> usize;
+
+    /// Return whether the underlying bitmap is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    fn get_bit(&self, pos: u64) -> bool;
+}
+
+/// Rank queries over a bitmap.
+pub trait BitRank: BitAccess {
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    fn rank(&self, pos: u64, bit: bool) -> u64;
+
+    /// Count the number of set bits in the underlying bitmap.
+    fn count_ones(&self) -> usize;
+
+    /// Count the number of unset bits in the underlying bitmap.
+    fn count_zeros(&self) -> usize {
+        self.len() - self.count_ones()
+    }
+
+    /// Query the `pos`th bit and compute the number of ones left of `pos`.  Implementations
+    /// may answer both at once faster than calling [`BitAccess::get_bit`] and
+    /// [`BitRank::rank`] separately.
+    fn bit_and_one_rank(&self, pos: u64) -> (bool, u64) {
+        (self.get_bit(pos), self.rank(pos, true))
+    }
+}
+
+/// Select queries over a bitmap.
+pub trait BitSelect: BitAccess {
+    /// Compute the position of the `rank`th zero (zero-indexed), returning `None` if there are
+    /// not `rank + 1` zeros in the array.
+    fn select0(&self, rank: u64) -> Option<u64>;
+
+    /// Compute the position of the `rank`th one (zero-indexed), returning `None` if there are
+    /// not `rank + 1` ones in the array.
+    fn select1(&self, rank: u64) -> Option<u64>;
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        if bit {
+            self.select1(rank)
+        } else {
+            self.select0(rank)
+        }
+    }
+}
+
+/// Iteration over the positions of the ones in a bitmap, in increasing order.
+///
+/// Use a higher-ranked bound like `for<'a> B: BitIterOnes<'a>` to require this in generic code.
+pub trait BitIterOnes<'a> {
+    /// Iterator over the positions of the ones.
+    type IterOnes: Iterator<Item = u64>;
+
+    /// Return an iterator over the positions of the ones.
+    fn iter_ones(&'a self) -> Self::IterOnes;
+}
+
+/// Rank and select queries shared by the crate's bitmap dictionaries, implemented for every type
+/// with both [`BitRank`] and [`BitSelect`].
+///
+/// ```
+/// use rsdict::{PlainRsDict, RankSelect, RsDict};
+///
+/// fn ones_before_last_one<R: RankSelect>(r: &R) -> Option<u64> {
+///     let last = r.select1(r.count_ones().checked_sub(1)? as u64)?;
+///     Some(r.rank(last, true))
+/// }
+///
+/// let blocks = vec![0b1011, 0b110];
+/// assert_eq!(ones_before_last_one(&RsDict::from_blocks(blocks.iter().cloned())), Some(4));
+/// assert_eq!(ones_before_last_one(&PlainRsDict::from_blocks(blocks.into_iter())), Some(4));
+/// ```
+pub trait RankSelect: BitRank + BitSelect {}
+
+impl<T: BitRank + BitSelect + ?Sized> RankSelect for T {}
+
+// Inherent methods take precedence over trait methods, so these all forward
+// to the type's own implementation.
+macro_rules! impl_bit_traits {
+    ($(impl$([$($generics:tt)*])? for $t:ty { $($rank_extra:tt)* })*) => {
+        $(
+            impl$(<$($generics)*>)? BitAccess for $t {
+                #[inline]
+                fn len(&self) -> usize {
+                    self.len()
+                }
+
+                #[inline]
+                fn get_bit(&self, pos: u64) -> bool {
+                    self.get_bit(pos)
+                }
+            }
+
+            impl$(<$($generics)*>)? BitRank for $t {
+                #[inline]
+                fn rank(&self, pos: u64, bit: bool) -> u64 {
+                    self.rank(pos, bit)
+                }
+
+                #[inline]
+                fn count_ones(&self) -> usize {
+                    self.count_ones()
+                }
+
+                #[inline]
+                fn count_zeros(&self) -> usize {
+                    self.count_zeros()
+                }
+
+                $($rank_extra)*
+            }
+
+            impl$(<$($generics)*>)? BitSelect for $t {
+                #[inline]
+                fn select0(&self, rank: u64) -> Option<u64> {
+                    self.select0(rank)
+                }
+
+                #[inline]
+                fn select1(&self, rank: u64) -> Option<u64> {
+                    self.select1(rank)
+                }
+            }
+        )*
+    };
+}
+
+impl_bit_traits! {
+    impl[const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock]
+        for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+    {
+        #[inline]
+        fn bit_and_one_rank(&self, pos: u64) -> (bool, u64) {
+            self.bit_and_one_rank(pos)
+        }
+    }
+    impl['a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64]
+        for RsDictSlice<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+    {
+        #[inline]
+        fn bit_and_one_rank(&self, pos: u64) -> (bool, u64) {
+            self.bit_and_one_rank(pos)
+        }
+    }
+    impl for PlainRsDict {}
+    impl for AutoRsDict {}
+    impl for InterleavedRsDict {}
+    impl for WideRsDict {}
+    impl for RleRsDict {}
+}
+
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock + 'a>
+    BitIterOnes<'a> for RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    type IterOnes = RsDictIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a, 'b, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> BitIterOnes<'a>
+    for RsDictSlice<'b, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    type IterOnes = RsDictSliceIterator<'b, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a> BitIterOnes<'a> for PlainRsDict {
+    type IterOnes = PlainIterOnes<'a>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a> BitIterOnes<'a> for AutoRsDict {
+    type IterOnes = AutoIterOnes<'a>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a> BitIterOnes<'a> for InterleavedRsDict {
+    type IterOnes = InterleavedIterOnes<'a>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a> BitIterOnes<'a> for WideRsDict {
+    type IterOnes = RsDictIterator<'a, DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, u128>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+impl<'a> BitIterOnes<'a> for RleRsDict {
+    type IterOnes = RleIterOnes<'a>;
+
+    #[inline]
+    fn iter_ones(&'a self) -> Self::IterOnes {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::{BitIterOnes, BitRank};
+    use crate::test_helpers::check_rank_select;
+    use crate::{AutoRsDict, InterleavedRsDict, PlainRsDict, RleRsDict, RsDict, WideRsDict};
+
+    fn ones<B: for<'a> BitIterOnes<'a> + BitRank>(r: &B) -> Vec<u64> {
+        r.iter_ones().collect()
+    }
+
+    #[quickcheck]
+    fn qc_traits(blocks: Vec<u64>) {
+        let bits: Vec<bool> = blocks
+            .iter()
+            .flat_map(|&b| (0..64).map(move |i| (b >> i) & 1 != 0))
+            .collect();
+        let expected: Vec<u64> = (0..bits.len() as u64)
+            .filter(|&i| bits[i as usize])
+            .collect();
+
+        let r = RsDict::from_blocks(blocks.iter().cloned());
+        check_rank_select!(&r, &bits);
+        assert_eq!(ones(&r), expected);
+
+        let plain = PlainRsDict::from_blocks(blocks.iter().cloned());
+        check_rank_select!(&plain, &bits);
+        assert_eq!(ones(&plain), expected);
+
+        let auto = AutoRsDict::from_blocks(blocks.iter().cloned());
+        check_rank_select!(&auto, &bits);
+        assert_eq!(ones(&auto), expected);
+
+        let interleaved = InterleavedRsDict::from_blocks(blocks.iter().cloned());
+        check_rank_select!(&interleaved, &bits);
+        assert_eq!(ones(&interleaved), expected);
+
+        let wide = WideRsDict::from_blocks(blocks.iter().cloned());
+        check_rank_select!(&wide, &bits);
+        assert_eq!(ones(&wide), expected);
+
+        let r = RsDict::<256, 512, u128>::from_blocks_with_block_sizes(blocks.iter().cloned());
+        check_rank_select!(&r, &bits);
+        assert_eq!(ones(&r), expected);
+
+        let rle = RleRsDict::from_blocks(blocks.into_iter());
+        check_rank_select!(&rle, &bits);
+        assert_eq!(ones(&rle), expected);
+    }
+}
@@ -0,0 +1,419 @@
+//! Run-length compressed variant of `RsDict` for bitmaps with long runs.
+//!
+//! Even though a small block of all zeros or all ones has an empty enumerative
+//! code, `RsDict` still pays a byte in `sb_classes` per small block and a
+//! `LargeBlock` per large block, so a billion-bit run of zeros costs about 16
+//! MB.  `RleRsDict` instead collapses every run of at least `MIN_RUN_BLOCKS`
+//! class-0 or class-64 small blocks into a single `Run` record and stores the
+//! remaining "literal" small blocks, concatenated, in a regular `RsDict`.
+//!
+//! Since short runs stay in the literal dictionary, a bitmap without any long
+//! runs is represented as a plain `RsDict` with an empty run table, so the
+//! constructor effectively picks the representation per region automatically.
+//!
+//! Queries binary search the run table for the last run starting at or before
+//! the query, and then either answer directly from the run or translate the
+//! query into the literal dictionary.
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::ops::Range;
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, SMALL_BLOCK_SIZE};
+use super::iter::RsDictIterator;
+use super::RsDict;
+
+// Only collapse runs of at least this many small blocks: a `Run` record costs
+// as much as the metadata for a large block's worth of literal blocks.
+const MIN_RUN_BLOCKS: u64 = DEFAULT_LARGE_BLOCK_SIZE / SMALL_BLOCK_SIZE;
+
+/// Rank and select over a bitmap with long runs of zeros or ones.
+///
+/// ```
+/// use rsdict::RleRsDict;
+///
+/// // A million zeros followed by a few mixed blocks.
+/// let blocks = std::iter::repeat(0).take(1_000_000 / 64).chain(vec![0b1011, 0b110]);
+/// let r = RleRsDict::from_blocks(blocks);
+/// assert_eq!(r.count_ones(), 5);
+/// assert_eq!(r.select1(3), Some(1_000_000 + 64 + 1));
+/// assert_eq!(r.rank(1_000_000 + 64, true), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RleRsDict {
+    len: u64,
+    num_ones: u64,
+    num_zeros: u64,
+
+    // Runs sorted by their start position.
+    runs: Vec<Run>,
+    // All of the bits not covered by a run.
+    literals: RsDict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Run {
+    // Position of the run's first bit, which is always small block aligned.
+    start: u64,
+    // Length of the run in bits, also a multiple of `SMALL_BLOCK_SIZE`.
+    len: u64,
+    bit: bool,
+
+    // Number of literal bits before `start`.
+    literal_before: u64,
+    // Number of ones before `start`, both total and only within runs.
+    ones_before: u64,
+    run_ones_before: u64,
+}
+
+impl Run {
+    #[inline]
+    fn end(&self) -> u64 {
+        self.start + self.len
+    }
+
+    #[inline]
+    fn count(&self, bit: bool) -> u64 {
+        if self.bit == bit {
+            self.len
+        } else {
+            0
+        }
+    }
+
+    // Number of `bit` values before `start`, total and only within runs.
+    #[inline]
+    fn before(&self, bit: bool) -> (u64, u64) {
+        if bit {
+            (self.ones_before, self.run_ones_before)
+        } else {
+            let run_bits_before = self.start - self.literal_before;
+            (
+                self.start - self.ones_before,
+                run_bits_before - self.run_ones_before,
+            )
+        }
+    }
+}
+
+impl RleRsDict {
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit
+    /// blocks, collapsing long runs of all-zero or all-one blocks.
+    pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
+        let mut builder = Builder::default();
+        for block in blocks {
+            builder.push_block(block);
+        }
+        builder.finish()
+    }
+
+    /// Return the length of the underlying bitmap.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the underlying bitmap is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count the number of set bits in the underlying bitmap.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.num_ones as usize
+    }
+
+    /// Count the number of unset bits in the underlying bitmap.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        self.num_zeros as usize
+    }
+
+    /// Return the number of runs collapsed out of the literal bitmap.
+    #[inline]
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Push a bit at the end of the underlying bitmap.  Pushed bits are
+    /// always stored as literals.
+    #[inline]
+    pub fn push(&mut self, bit: bool) {
+        self.literals.push(bit);
+        if bit {
+            self.num_ones += 1;
+        } else {
+            self.num_zeros += 1;
+        }
+        self.len += 1;
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let run = match self.run_before(pos) {
+            Some(run) => run,
+            None => return self.literals.rank(pos, bit),
+        };
+        let (before, run_before) = run.before(bit);
+        if pos < run.end() {
+            return before + if run.bit == bit { pos - run.start } else { 0 };
+        }
+        let literal_pos = run.literal_before + (pos - run.end());
+        run_before + run.count(bit) + self.literal_rank(literal_pos, bit)
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    pub fn get_bit(&self, pos: u64) -> bool {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        match self.run_before(pos) {
+            Some(run) if pos < run.end() => run.bit,
+            Some(run) => self
+                .literals
+                .get_bit(run.literal_before + (pos - run.end())),
+            None => self.literals.get_bit(pos),
+        }
+    }
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        let total = if bit { self.num_ones } else { self.num_zeros };
+        if rank >= total {
+            return None;
+        }
+        // Find the last run with at most `rank` instances of `bit` before it.
+        let ix = self.runs.partition_point(|r| r.before(bit).0 <= rank);
+        if ix == 0 {
+            return self.literals.select(rank, bit);
+        }
+        let run = &self.runs[ix - 1];
+        let (before, run_before) = run.before(bit);
+        if run.bit == bit && rank < before + run.len {
+            return Some(run.start + (rank - before));
+        }
+        let literal_rank = rank - run_before - run.count(bit);
+        let literal_pos = self.literals.select(literal_rank, bit)?;
+        Some(run.end() + (literal_pos - run.literal_before))
+    }
+
+    /// Specialized version of [`RleRsDict::select`] for finding positions of zeros.
+    #[inline]
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        self.select(rank, false)
+    }
+
+    /// Specialized version of [`RleRsDict::select`] for finding positions of ones.
+    #[inline]
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        self.select(rank, true)
+    }
+
+    /// Return an iterator over the positions of the ones in the underlying bitmap.  Runs of
+    /// ones are returned without touching the literal dictionary.
+    #[inline]
+    pub fn iter(&self) -> RleIterOnes<'_> {
+        RleIterOnes {
+            runs: &self.runs,
+            literals: self.literals.iter().peekable(),
+            run_bits_before: 0,
+            run_ones: 0..0,
+        }
+    }
+
+    // Find the last run starting at or before `pos`.
+    #[inline]
+    fn run_before(&self, pos: u64) -> Option<&Run> {
+        let ix = self.runs.partition_point(|r| r.start <= pos);
+        ix.checked_sub(1).map(|ix| &self.runs[ix])
+    }
+
+    // `RsDict::rank` over the literals with `pos == len` allowed.
+    #[inline]
+    fn literal_rank(&self, pos: u64, bit: bool) -> u64 {
+        if pos == self.literals.len() as u64 {
+            if bit {
+                self.literals.count_ones() as u64
+            } else {
+                self.literals.count_zeros() as u64
+            }
+        } else {
+            self.literals.rank(pos, bit)
+        }
+    }
+}
+
+/// Iterator over the positions of the ones in an `RleRsDict`.
+#[derive(Debug)]
+pub struct RleIterOnes<'a> {
+    // Runs we haven't reached yet.
+    runs: &'a [Run],
+    literals: Peekable<RsDictIterator<'a>>,
+    // Number of run bits before the next literal, i.e. what to add to a
+    // literal position to get its position in the bitmap.
+    run_bits_before: u64,
+    // Ones of the current run we haven't returned yet.
+    run_ones: Range<u64>,
+}
+
+impl<'a> Iterator for RleIterOnes<'a> {
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        loop {
+            if let Some(pos) = self.run_ones.next() {
+                return Some(pos);
+            }
+            // Return literal ones up to the next run, and then move past it.
+            let literal = self.literals.peek().copied();
+            match self.runs.split_first() {
+                Some((run, _)) if literal.map_or(true, |l| l >= run.literal_before) => {
+                    if run.bit {
+                        self.run_ones = run.start..run.end();
+                    }
+                    self.run_bits_before = run.end() - run.literal_before;
+                    self.runs = &self.runs[1..];
+                }
+                _ => {
+                    self.literals.next();
+                    return literal.map(|l| l + self.run_bits_before);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    len: u64,
+    num_ones: u64,
+    run_ones: u64,
+    runs: Vec<Run>,
+    literal_blocks: Vec<u64>,
+
+    // The current uniform block we've seen `pending` times in a row but
+    // haven't yet decided whether to collapse into a run.
+    pending_block: u64,
+    pending: u64,
+}
+
+impl Builder {
+    fn push_block(&mut self, block: u64) {
+        let uniform = block == 0 || block == u64::MAX;
+        if self.pending > 0 && (!uniform || block != self.pending_block) {
+            self.flush_pending();
+        }
+        if uniform {
+            self.pending_block = block;
+            self.pending += 1;
+        } else {
+            self.literal_blocks.push(block);
+            self.len += SMALL_BLOCK_SIZE;
+            self.num_ones += block.count_ones() as u64;
+        }
+    }
+
+    fn flush_pending(&mut self) {
+        let bit = self.pending_block != 0;
+        let run_len = self.pending * SMALL_BLOCK_SIZE;
+        if self.pending >= MIN_RUN_BLOCKS {
+            let literal_bits = self.literal_blocks.len() as u64 * SMALL_BLOCK_SIZE;
+            self.runs.push(Run {
+                start: self.len,
+                len: run_len,
+                bit,
+                literal_before: literal_bits,
+                ones_before: self.num_ones,
+                run_ones_before: self.run_ones,
+            });
+            if bit {
+                self.run_ones += run_len;
+            }
+        } else {
+            for _ in 0..self.pending {
+                self.literal_blocks.push(self.pending_block);
+            }
+        }
+        self.len += run_len;
+        if bit {
+            self.num_ones += run_len;
+        }
+        self.pending = 0;
+    }
+
+    fn finish(mut self) -> RleRsDict {
+        if self.pending > 0 {
+            self.flush_pending();
+        }
+        RleRsDict {
+            len: self.len,
+            num_ones: self.num_ones,
+            num_zeros: self.len - self.num_ones,
+            runs: self.runs,
+            literals: RsDict::from_blocks(self.literal_blocks.into_iter()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use super::RleRsDict;
+    use crate::test_helpers::{check_rank_select, hash_u64};
+
+    // Generate blocks with a mix of long and short runs of zeros and ones.
+    fn test_blocks(seeds: Vec<(u8, u64)>) -> Vec<u64> {
+        let mut blocks = vec![];
+        for (run, seed) in seeds.into_iter().take(24) {
+            let block = match seed % 3 {
+                0 => 0,
+                1 => u64::MAX,
+                _ => hash_u64(seed),
+            };
+            for i in 0..(run % 24) {
+                blocks.push(if block == 0 || block == u64::MAX {
+                    block
+                } else {
+                    hash_u64(block.wrapping_add(i as u64))
+                });
+            }
+        }
+        blocks
+    }
+
+    #[quickcheck]
+    fn qc_rle_rsdict(seeds: Vec<(u8, u64)>, tail: Vec<bool>) {
+        let blocks = test_blocks(seeds);
+        let mut bits = vec![];
+        for &block in &blocks {
+            bits.extend((0..64).map(|i| (block >> i) & 1 != 0));
+        }
+        let mut r = RleRsDict::from_blocks(blocks.into_iter());
+        for &bit in &tail {
+            r.push(bit);
+            bits.push(bit);
+        }
+        check_rank_select!(&r, &bits);
+        let ones: Vec<u64> = (0..bits.len() as u64)
+            .filter(|&i| bits[i as usize])
+            .collect();
+        assert_eq!(r.iter().collect::<Vec<_>>(), ones);
+    }
+
+    #[test]
+    fn test_long_runs_are_collapsed() {
+        let blocks = (0..4096).map(|i| if i < 2048 { 0 } else { u64::MAX });
+        let r = RleRsDict::from_blocks(blocks);
+        assert_eq!(r.num_runs(), 2);
+        assert_eq!(r.literals.len(), 0);
+        assert_eq!(r.rank(2048 * 64 + 10, true), 10);
+        assert_eq!(r.select0(2047 * 64), Some(2047 * 64));
+    }
+}
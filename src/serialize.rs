@@ -0,0 +1,416 @@
+//! A compact, portable byte format for `RsDict`.
+//!
+//! All integers are little-endian:
+//!
+//! | Field      | Type                     | Notes                                         |
+//! |------------|--------------------------|-----------------------------------------------|
+//! | magic      | `[u8; 4]`                | `b"RSDC"`                                     |
+//! | version    | `u16`                    | Currently 1                                   |
+//! | flags      | `u16`                    | Bit 0: select1 index, bit 1: select0 index    |
+//! | len        | `u64`                    | Number of bits                                |
+//! | code_len   | `u64`                    | Total length of the enumerative codes in bits |
+//! | classes    | `[u8; num_sb]`           | `num_sb = (len - 1) / 64`, or 0 if empty      |
+//! | codes      | `[u64; ⌈code_len / 64⌉]` | Enumerative codes, packed LSB first           |
+//! | last_block | `u64`                    | Only present if `len > 0`                     |
+//! | checksum   | `u64`                    | [`RsDict::fingerprint`] of the dictionary     |
+//!
+//! Only the compressed bitmap is stored.  The large block and select indices
+//! are rebuilt from the classes when loading, which doesn't decode any codes
+//! and lets dictionaries with different block sizes read each other's bytes.
+use super::constants::SMALL_BLOCK_SIZE;
+use super::enum_code::{self, ENUM_CODE_LENGTH};
+use super::{LargeBlock, LastBlock, RsDict, SmallBlock, VarintBuffer};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+const MAGIC: &[u8; 4] = b"RSDC";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 4 + 2 + 2 + 8 + 8;
+const CHECKSUM_LEN: usize = 8;
+
+const FLAG_SELECT1_INDEX: u16 = 1 << 0;
+const FLAG_SELECT0_INDEX: u16 = 1 << 1;
+
+/// Error returned by [`RsDict::from_bytes`] when its input isn't a valid serialized dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FromBytesError {
+    /// The input ended in the middle of the dictionary.
+    Truncated,
+    /// The input doesn't start with the format's magic bytes.
+    BadMagic,
+    /// The input was written with an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The input's fields are inconsistent, e.g. a code that doesn't match its class.
+    Corrupt,
+    /// There are bytes left over after the dictionary.
+    TrailingBytes,
+    /// The dictionary's fingerprint doesn't match the checksum stored with it.
+    ChecksumMismatch,
+}
+
+impl fmt::Display for FromBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromBytesError::Truncated => write!(f, "serialized RsDict is truncated"),
+            FromBytesError::BadMagic => write!(f, "input is not a serialized RsDict"),
+            FromBytesError::UnsupportedVersion(v) => {
+                write!(f, "unsupported RsDict format version {}", v)
+            }
+            FromBytesError::Corrupt => write!(f, "serialized RsDict is corrupt"),
+            FromBytesError::TrailingBytes => write!(f, "trailing bytes after serialized RsDict"),
+            FromBytesError::ChecksumMismatch => write!(f, "serialized RsDict checksum mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FromBytesError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], FromBytesError> {
+        if self.bytes.len() < n {
+            return Err(FromBytesError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, FromBytesError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, FromBytesError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+impl RsDict {
+    /// Deserialize a dictionary written by [`RsDict::to_bytes`].  Every code is checked against
+    /// its class and the whole dictionary against its stored [`RsDict::fingerprint`], so corrupt
+    /// input returns an error rather than a dictionary that gives wrong answers.
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        Self::from_bytes_with_block_sizes(bytes)
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Return the length of [`RsDict::to_bytes`]'s output.
+    pub fn serialized_len(&self) -> usize {
+        let last_block_len = if self.len > 0 { 8 } else { 0 };
+        HEADER_LEN
+            + self.sb_classes.len()
+            + self.sb_indices.buf.len() * 8
+            + last_block_len
+            + CHECKSUM_LEN
+    }
+
+    /// Serialize the dictionary into a portable byte format, which [`RsDict::from_bytes`] reads
+    /// back.  The bytes don't depend on the block sizes or the platform.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// let r = RsDict::from_blocks([0xdead_beef, 0, u64::MAX].iter().copied());
+    /// let bytes = r.to_bytes();
+    /// assert_eq!(bytes.len(), r.serialized_len());
+    ///
+    /// let copy = RsDict::from_bytes(&bytes).unwrap();
+    /// assert_eq!(copy, r);
+    ///
+    /// // Dictionaries with other block sizes can read the same bytes.
+    /// let wide = RsDict::<4096, 16384>::from_bytes_with_block_sizes(&bytes).unwrap();
+    /// assert_eq!(wide.rank(100, true), r.rank(100, true));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.serialized_len());
+        let mut flags = 0;
+        if self.select1_index {
+            flags |= FLAG_SELECT1_INDEX;
+        }
+        if self.select0_index {
+            flags |= FLAG_SELECT0_INDEX;
+        }
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out.extend_from_slice(&(self.sb_indices.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.sb_classes);
+        for word in &self.sb_indices.buf {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        if self.len > 0 {
+            out.extend_from_slice(&self.last_block.bits.to_le_bytes());
+        }
+        out.extend_from_slice(&self.fingerprint().to_le_bytes());
+        out
+    }
+
+    /// Same as [`RsDict::from_bytes`] but with custom block sizes, which needn't match the ones
+    /// the dictionary was serialized with.
+    pub fn from_bytes_with_block_sizes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        let mut reader = Reader { bytes };
+        let magic = reader
+            .take(MAGIC.len())
+            .map_err(|_| FromBytesError::BadMagic)?;
+        if magic != MAGIC {
+            return Err(FromBytesError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(FromBytesError::UnsupportedVersion(version));
+        }
+        let flags = reader.u16()?;
+        if flags & !(FLAG_SELECT1_INDEX | FLAG_SELECT0_INDEX) != 0 {
+            return Err(FromBytesError::Corrupt);
+        }
+        let len = reader.u64()?;
+        let code_len = reader.u64()?;
+
+        let num_sb = if len > 0 {
+            (len - 1) / SMALL_BLOCK_SIZE
+        } else {
+            0
+        };
+        let num_sb = usize::try_from(num_sb).map_err(|_| FromBytesError::Truncated)?;
+        let sb_classes = reader.take(num_sb)?;
+        let mut expected_code_len = 0u64;
+        for &sb_class in sb_classes {
+            let code_length = ENUM_CODE_LENGTH
+                .get(sb_class as usize)
+                .ok_or(FromBytesError::Corrupt)?;
+            expected_code_len += *code_length as u64;
+        }
+        if code_len != expected_code_len {
+            return Err(FromBytesError::Corrupt);
+        }
+
+        let num_words = ((code_len + 63) / 64) as usize;
+        let buf = reader
+            .take(num_words * 8)?
+            .chunks_exact(8)
+            .map(|chunk| {
+                let mut word = [0; 8];
+                word.copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+        let sb_indices = VarintBuffer {
+            buf,
+            len: code_len as usize,
+        };
+        // `VarintBuffer::push` never sets bits past the end.
+        if code_len % 64 != 0 && sb_indices.buf[num_words - 1] >> (code_len % 64) != 0 {
+            return Err(FromBytesError::Corrupt);
+        }
+
+        let mut last_block = LastBlock::new();
+        if len > 0 {
+            let bits = reader.u64()?;
+            let last_len = len - num_sb as u64 * SMALL_BLOCK_SIZE;
+            if last_len < SMALL_BLOCK_SIZE && bits >> last_len != 0 {
+                return Err(FromBytesError::Corrupt);
+            }
+            last_block.bits = bits;
+            last_block.num_ones = bits.count_ones() as u64;
+            last_block.num_zeros = last_len - last_block.num_ones;
+        }
+        let checksum = reader.u64()?;
+        if !reader.bytes.is_empty() {
+            return Err(FromBytesError::TrailingBytes);
+        }
+
+        let mut pointer = 0;
+        for &sb_class in sb_classes {
+            let code_length = ENUM_CODE_LENGTH[sb_class as usize] as usize;
+            if !enum_code::is_valid(sb_indices.get(pointer, code_length), sb_class) {
+                return Err(FromBytesError::Corrupt);
+            }
+            pointer += code_length;
+        }
+
+        let mut classes = Vec::with_capacity(Self::sb_classes_padded_len(sb_classes.len()));
+        classes.extend_from_slice(sb_classes);
+        let r = Self::from_parts(
+            classes,
+            sb_indices,
+            last_block,
+            flags & FLAG_SELECT1_INDEX != 0,
+            flags & FLAG_SELECT0_INDEX != 0,
+        );
+        if checksum != r.fingerprint() {
+            return Err(FromBytesError::ChecksumMismatch);
+        }
+        Ok(r)
+    }
+
+    // Assemble a dictionary from its compressed blocks, which must be
+    // consistent, and rebuild its indices.
+    pub(super) fn from_parts(
+        mut sb_classes: Vec<u8>,
+        sb_indices: VarintBuffer,
+        last_block: LastBlock,
+        select1_index: bool,
+        select0_index: bool,
+    ) -> Self {
+        let mut r = Self::with_capacity_and_indices(0, select1_index, select0_index);
+        r.rebuild_indices(&sb_classes, &last_block);
+        Self::reserve_sb_classes_padding(&mut sb_classes, 0);
+        r.len = r.num_ones + r.num_zeros;
+        r.sb_classes = sb_classes;
+        r.sb_indices = sb_indices;
+        r.last_block = last_block;
+        r
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    // Fill in the large blocks, select indices and counts of an empty
+    // dictionary from its classes and last block, which must be consistent.
+    pub(super) fn rebuild_indices(&mut self, sb_classes: &[u8], last_block: &LastBlock<C>) {
+        let last_len = last_block.num_ones + last_block.num_zeros;
+        let blocks = sb_classes
+            .iter()
+            .map(|&sb_class| (sb_class as u64, C::BITS))
+            .chain(if last_len > 0 {
+                Some((last_block.num_ones, last_len))
+            } else {
+                None
+            });
+        let mut pointer = 0;
+        for (i, (ones, block_len)) in blocks.enumerate() {
+            if i as u64 % Self::SMALL_BLOCK_PER_LARGE_BLOCK == 0 {
+                self.large_blocks.push(LargeBlock {
+                    pointer,
+                    rank: self.num_ones,
+                });
+            }
+            if i < sb_classes.len() {
+                pointer += C::code_length(ones as u8) as u64;
+            }
+
+            // As in `from_blocks_impl`, check whether a multiple of
+            // `SELECT_BLOCK_SIZE` falls within this block's ones (or zeros).
+            let lb_start = i as u64 * C::BITS / LARGE_BLOCK_SIZE;
+            let zeros = block_len - ones;
+            if self.select1_index
+                && (self.num_ones + SELECT_BLOCK_SIZE - 1) / SELECT_BLOCK_SIZE
+                    != (self.num_ones + SELECT_BLOCK_SIZE + ones - 1) / SELECT_BLOCK_SIZE
+            {
+                self.select_one_inds.push(lb_start);
+            }
+            if self.select0_index
+                && (self.num_zeros + SELECT_BLOCK_SIZE - 1) / SELECT_BLOCK_SIZE
+                    != (self.num_zeros + SELECT_BLOCK_SIZE + zeros - 1) / SELECT_BLOCK_SIZE
+            {
+                self.select_zero_inds.push(lb_start);
+            }
+            self.num_ones += ones;
+            self.num_zeros += zeros;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FromBytesError;
+    use crate::test_helpers::test_block;
+    use crate::{RsDict, RsDictBuilder};
+    use alloc::vec::Vec;
+
+    #[quickcheck]
+    fn qc_roundtrip(blocks: Vec<u64>, tail: Vec<bool>, sparse: bool) {
+        let blocks = blocks.into_iter().map(|b| test_block(b, sparse));
+        let mut r = RsDict::from_blocks(blocks);
+        for bit in tail {
+            r.push(bit);
+        }
+        let bytes = r.to_bytes();
+        assert_eq!(bytes.len(), r.serialized_len());
+        assert_eq!(RsDict::from_bytes(&bytes), Ok(r.clone()));
+
+        let small = RsDict::<64, 128>::from_bytes_with_block_sizes(&bytes).unwrap();
+        assert_eq!(small.len(), r.len());
+        for i in 0..r.len() as u64 {
+            assert_eq!(small.rank(i, true), r.rank(i, true));
+        }
+        for i in 0..=r.count_ones() as u64 {
+            assert_eq!(small.select1(i), r.select1(i));
+        }
+        for i in 0..=r.count_zeros() as u64 {
+            assert_eq!(small.select0(i), r.select0(i));
+        }
+
+        for i in 0..bytes.len() {
+            assert!(RsDict::from_bytes(&bytes[..i]).is_err());
+        }
+        let mut extra = bytes;
+        extra.push(0);
+        assert_eq!(
+            RsDict::from_bytes(&extra),
+            Err(FromBytesError::TrailingBytes)
+        );
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let r = RsDict::from_blocks([0b1011, 0, 1 << 63].iter().copied());
+        let bytes = r.to_bytes();
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert_eq!(RsDict::from_bytes(&bad), Err(FromBytesError::BadMagic));
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert_eq!(
+            RsDict::from_bytes(&bad),
+            Err(FromBytesError::UnsupportedVersion(2))
+        );
+
+        assert_eq!(
+            RsDict::from_bytes(&bytes[..bytes.len() - 8]),
+            Err(FromBytesError::Truncated)
+        );
+
+        // Flipping the low bit of the first block's code gives another valid
+        // code of the same class, which only the checksum catches.
+        let mut bad = bytes.clone();
+        bad[26] ^= 1;
+        assert_eq!(
+            RsDict::from_bytes(&bad),
+            Err(FromBytesError::ChecksumMismatch)
+        );
+
+        let unindexed = RsDictBuilder::new()
+            .select0_index(false)
+            .from_blocks([0b1011, 0, 1 << 63].iter().copied());
+        assert_eq!(RsDict::from_bytes(&unindexed.to_bytes()), Ok(unindexed));
+
+        // A class that's out of range.
+        let mut bad = bytes.clone();
+        bad[24] = 65;
+        assert_eq!(RsDict::from_bytes(&bad), Err(FromBytesError::Corrupt));
+
+        // The first block's code is 16 bits but not less than B(64, 3).
+        let mut bad = bytes;
+        bad[26..34].copy_from_slice(&0xffffu64.to_le_bytes());
+        assert_eq!(RsDict::from_bytes(&bad), Err(FromBytesError::Corrupt));
+    }
+}
@@ -0,0 +1,289 @@
+//! Borrowed views of a range of an `RsDict`.
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE};
+use super::iter::RsDictIterator;
+use super::rank_by_bit;
+use super::RsDict;
+use core::ops::{Bound, RangeBounds};
+
+/// A borrowed view of the bits in `start..end` of an [`RsDict`], created with [`RsDict::slice`].
+///
+/// Positions and ranks are relative to the start of the slice, so the slice behaves like a
+/// bitmap of its own.  Queries go through the parent's rank and select with an offset, so
+/// creating a slice only costs two rank queries and doesn't copy any bits.
+///
+/// ```
+/// use rsdict::RsDict;
+///
+/// // Two documents' bits, concatenated.
+/// let r: RsDict = [true, false, true, true, false, false, true].iter().copied().collect();
+/// let doc = r.slice(2..6);
+/// assert_eq!(doc.len(), 4);
+/// assert_eq!(doc.count_ones(), 2);
+/// assert_eq!(doc.rank(2, true), 2);
+/// assert_eq!(doc.select0(0), Some(2));
+/// assert_eq!(doc.iter().collect::<Vec<_>>(), vec![0, 1]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RsDictSlice<
+    'a,
+    const LARGE_BLOCK_SIZE: u64 = { DEFAULT_LARGE_BLOCK_SIZE },
+    const SELECT_BLOCK_SIZE: u64 = { DEFAULT_SELECT_BLOCK_SIZE },
+> {
+    parent: &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>,
+    start: u64,
+    len: u64,
+    // Number of ones in the parent before `start` and within the slice.
+    start_ones: u64,
+    num_ones: u64,
+}
+
+// Resolve `range` against a bitmap of length `len`, panicking like slice
+// indexing if it's out of bounds.
+fn resolve_range(range: impl RangeBounds<u64>, len: u64) -> (u64, u64) {
+    let start = match range.start_bound() {
+        Bound::Included(&s) => s,
+        Bound::Excluded(&s) => s.checked_add(1).expect("Range start overflows u64"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&e) => e.checked_add(1).expect("Range end overflows u64"),
+        Bound::Excluded(&e) => e,
+        Bound::Unbounded => len,
+    };
+    if start > end {
+        panic!("Slice starts at {} but ends at {}", start, end);
+    }
+    if end > len {
+        panic!("Out of bounds slice end: {} > {}", end, len);
+    }
+    (start, end)
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Return a view of the bits in `range` whose positions and ranks are relative to the start
+    /// of the range.  Panics if the range is out of bounds or starts after it ends.
+    pub fn slice(
+        &self,
+        range: impl RangeBounds<u64>,
+    ) -> RsDictSlice<'_, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        let (start, end) = resolve_range(range, self.len);
+        let start_ones = self.one_rank_or_count(start);
+        RsDictSlice {
+            parent: self,
+            start,
+            len: end - start,
+            start_ones,
+            num_ones: self.one_rank_or_count(end) - start_ones,
+        }
+    }
+
+    // `rank(pos, true)`, but also allowing `pos == len`.
+    #[inline]
+    fn one_rank_or_count(&self, pos: u64) -> u64 {
+        if pos == self.len {
+            self.num_ones
+        } else {
+            self.rank(pos, true)
+        }
+    }
+}
+
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDictSlice<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Return the dictionary this is a slice of.
+    #[inline]
+    pub fn parent(&self) -> &'a RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        self.parent
+    }
+
+    /// Return the position in the parent dictionary where the slice starts.
+    #[inline]
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Return a view of `range` within this slice, with positions relative to this slice's
+    /// start.  Panics if the range is out of bounds or starts after it ends.
+    pub fn slice(&self, range: impl RangeBounds<u64>) -> Self {
+        let (start, end) = resolve_range(range, self.len);
+        self.parent.slice(self.start + start..self.start + end)
+    }
+
+    /// Return the number of bits in the slice.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the slice is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Count the number of set bits in the slice.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.num_ones as usize
+    }
+
+    /// Count the number of unset bits in the slice.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        (self.len - self.num_ones) as usize
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the slice.  Panics if `pos` is out-of-bounds.
+    #[inline]
+    pub fn get_bit(&self, pos: u64) -> bool {
+        self.check_bounds(pos);
+        self.parent.get_bit(self.start + pos)
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos` in the slice. Panics if
+    /// `pos` is out-of-bounds.
+    #[inline]
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        self.check_bounds(pos);
+        let one_rank = self.parent.rank(self.start + pos, true) - self.start_ones;
+        rank_by_bit(one_rank, pos, bit)
+    }
+
+    /// Query the `pos`th bit of the slice and the number of set bits to the left of `pos` in a
+    /// single operation.  Panics if `pos` is out-of-bounds.
+    #[inline]
+    pub fn bit_and_one_rank(&self, pos: u64) -> (bool, u64) {
+        self.check_bounds(pos);
+        let (bit, one_rank) = self.parent.bit_and_one_rank(self.start + pos);
+        (bit, one_rank - self.start_ones)
+    }
+
+    /// Compute the position within the slice of its `rank`th instance of `bit` (zero-indexed),
+    /// returning `None` if there are not `rank + 1` instances of `bit` in the slice.
+    #[inline]
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        if bit {
+            self.select1(rank)
+        } else {
+            self.select0(rank)
+        }
+    }
+
+    /// Specialized version of [`RsDictSlice::select`] for finding positions of zeros.
+    #[inline]
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        if rank >= self.count_zeros() as u64 {
+            return None;
+        }
+        let start_zeros = self.start - self.start_ones;
+        self.parent
+            .select0(start_zeros + rank)
+            .map(|pos| pos - self.start)
+    }
+
+    /// Specialized version of [`RsDictSlice::select`] for finding positions of ones.
+    #[inline]
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        if rank >= self.num_ones {
+            return None;
+        }
+        self.parent
+            .select1(self.start_ones + rank)
+            .map(|pos| pos - self.start)
+    }
+
+    /// Return an iterator over the positions of the ones in the slice, relative to its start.
+    pub fn iter(&self) -> RsDictSliceIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        RsDictSliceIterator {
+            inner: self.parent.iter_in_range(self.start..self.start + self.len),
+            start: self.start,
+        }
+    }
+
+    #[inline]
+    fn check_bounds(&self, pos: u64) {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+    }
+}
+
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> IntoIterator
+    for &RsDictSlice<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    type Item = u64;
+    type IntoIter = RsDictSliceIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the positions of the ones in an [`RsDictSlice`], relative to its start.
+#[derive(Debug)]
+pub struct RsDictSliceIterator<
+    'a,
+    const LARGE_BLOCK_SIZE: u64 = { DEFAULT_LARGE_BLOCK_SIZE },
+    const SELECT_BLOCK_SIZE: u64 = { DEFAULT_SELECT_BLOCK_SIZE },
+> {
+    inner: RsDictIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>,
+    start: u64,
+}
+
+impl<'a, const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64> Iterator
+    for RsDictSliceIterator<'a, LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    type Item = u64;
+
+    #[inline]
+    fn next(&mut self) -> Option<u64> {
+        self.inner.next().map(|pos| pos - self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{check_rank_select, test_block};
+    use crate::{BitIterOnes, RsDict};
+    use alloc::vec::Vec;
+
+    #[quickcheck]
+    fn qc_slice(blocks: Vec<u64>, tail: Vec<bool>, start: u64, end: u64) {
+        let mut r = RsDict::<128, 256>::from_blocks_with_block_sizes(
+            blocks.into_iter().map(|b| test_block(b, true)),
+        );
+        for bit in tail {
+            r.push(bit);
+        }
+        let bits: Vec<bool> = (0..r.len() as u64).map(|i| r.get_bit(i)).collect();
+        let len = r.len() as u64;
+        let (start, end) = (start % (len + 1), end % (len + 1));
+        let (start, end) = (start.min(end), start.max(end));
+        let slice_bits = &bits[start as usize..end as usize];
+
+        let slice = r.slice(start..end);
+        assert_eq!(slice.start(), start);
+        check_rank_select!(&slice, slice_bits);
+        let expected: Vec<u64> = (0..slice_bits.len() as u64)
+            .filter(|&i| slice_bits[i as usize])
+            .collect();
+        assert_eq!(slice.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(slice.iter_ones().collect::<Vec<_>>(), expected);
+
+        let mid = (end - start) / 2;
+        let half = slice.slice(mid..);
+        check_rank_select!(&half, &slice_bits[mid as usize..]);
+        check_rank_select!(&r.slice(..), &bits);
+        check_rank_select!(&r.slice(start..start), &[]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_slice_out_of_bounds() {
+        let r: RsDict = [true, false].iter().copied().collect();
+        r.slice(1..3);
+    }
+}
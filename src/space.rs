@@ -0,0 +1,170 @@
+//! Memory accounting for `RsDict`.
+use super::{LargeBlock, RsDict, SmallBlock};
+use core::f64::consts::LN_2;
+use core::mem;
+
+/// Heap memory used by each part of an [`RsDict`], returned by [`RsDict::space_breakdown`].
+///
+/// The byte counts for each field only include the elements in use, and everything else a
+/// field's allocation holds is counted in `unused_capacity`.  This includes the padding at the
+/// end of `sb_classes` that rank reads into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpaceBreakdown {
+    /// Bytes of small block classes.
+    pub sb_classes: usize,
+    /// Bytes of enumerative codes for the small blocks.
+    pub sb_indices: usize,
+    /// Bytes of large block pointers and ranks.
+    pub large_blocks: usize,
+    /// Bytes of the select index for ones.
+    pub select_one_inds: usize,
+    /// Bytes of the select index for zeros.
+    pub select_zero_inds: usize,
+    /// Bytes allocated but not used by any of the above.
+    pub unused_capacity: usize,
+    /// Heap bits per bit of the bitmap, or `NaN` if it's empty.
+    pub bits_per_bit: f64,
+    /// Heap bits per bit of the bitmap's zeroth-order entropy, `len * H0(ones / len)`.  This is
+    /// infinite if the bitmap is all zeros or all ones and `NaN` if it's empty.
+    pub bits_per_entropy_bit: f64,
+}
+
+impl SpaceBreakdown {
+    /// Return the total heap memory in bytes, which is the same as [`RsDict::heap_size_bytes`].
+    #[inline]
+    pub fn total_bytes(&self) -> usize {
+        self.sb_classes
+            + self.sb_indices
+            + self.large_blocks
+            + self.select_one_inds
+            + self.select_zero_inds
+            + self.unused_capacity
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    /// Return the number of bytes the dictionary has allocated on the heap, including unused
+    /// capacity.  This doesn't include `size_of::<RsDict>()` itself.
+    #[inline]
+    pub fn heap_size_bytes(&self) -> usize {
+        self.sb_classes.capacity()
+            + self.sb_indices.buf.capacity() * mem::size_of::<u64>()
+            + self.large_blocks.capacity() * mem::size_of::<LargeBlock>()
+            + self.select_one_inds.capacity() * mem::size_of::<u64>()
+            + self.select_zero_inds.capacity() * mem::size_of::<u64>()
+    }
+
+    /// Break down [`RsDict::heap_size_bytes`] by field and compare it to the size of the bitmap.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// // A sparse bitmap takes less than a bit per bit.
+    /// let r = RsDict::from_blocks((0..1000).map(|i| 1 << (i % 64)));
+    /// let space = r.space_breakdown();
+    /// assert_eq!(space.total_bytes(), r.heap_size_bytes());
+    /// assert!(space.bits_per_bit < 1.0);
+    /// assert!(space.bits_per_entropy_bit > 1.0);
+    /// ```
+    pub fn space_breakdown(&self) -> SpaceBreakdown {
+        let sb_classes = self.sb_classes.len();
+        let sb_indices = self.sb_indices.buf.len() * mem::size_of::<u64>();
+        let large_blocks = self.large_blocks.len() * mem::size_of::<LargeBlock>();
+        let select_one_inds = self.select_one_inds.len() * mem::size_of::<u64>();
+        let select_zero_inds = self.select_zero_inds.len() * mem::size_of::<u64>();
+        let total = self.heap_size_bytes();
+        let unused_capacity =
+            total - sb_classes - sb_indices - large_blocks - select_one_inds - select_zero_inds;
+
+        let total_bits = total as f64 * 8.0;
+        let len = self.len as f64;
+        let entropy_bits =
+            entropy_term(self.num_ones, self.len) + entropy_term(self.num_zeros, self.len);
+        SpaceBreakdown {
+            sb_classes,
+            sb_indices,
+            large_blocks,
+            select_one_inds,
+            select_zero_inds,
+            unused_capacity,
+            bits_per_bit: total_bits / len,
+            bits_per_entropy_bit: total_bits / entropy_bits,
+        }
+    }
+}
+
+// One of the two terms of `n * H0`, `count * log2(n / count)`.
+fn entropy_term(count: u64, n: u64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    count as f64 * log2(n as f64 / count as f64)
+}
+
+// `f64::log2` needs `std`, so take the exponent from the float's bits and sum
+// the series `ln(m) = 2 * atanh(z)` with `z = (m - 1) / (m + 1)` for the
+// mantissa `m` in `[1, 2)`.  Since `z <= 1/3`, twenty terms are plenty for
+// the positive, normal inputs we have.
+fn log2(x: f64) -> f64 {
+    debug_assert!(x.is_normal() && x > 0.0);
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mantissa = f64::from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+
+    let z = (mantissa - 1.0) / (mantissa + 1.0);
+    let (mut power, mut atanh) = (z, 0.0);
+    for i in 0..20 {
+        atanh += power / (2 * i + 1) as f64;
+        power *= z * z;
+    }
+    exponent as f64 + 2.0 * atanh / LN_2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::log2;
+    use crate::test_helpers::hash_u64;
+    use crate::RsDict;
+    use alloc::vec::Vec;
+
+    #[quickcheck]
+    fn qc_log2(x: u64) -> bool {
+        let x = (hash_u64(x) >> 11) as f64 + 1.0;
+        (log2(x) - x.log2()).abs() < 1e-12
+    }
+
+    #[quickcheck]
+    fn qc_space_breakdown(blocks: Vec<u64>, tail: Vec<bool>) {
+        let mut r = RsDict::from_blocks(blocks.into_iter());
+        for bit in tail {
+            r.push(bit);
+        }
+        let space = r.space_breakdown();
+        assert_eq!(space.total_bytes(), r.heap_size_bytes());
+        assert_eq!(space.sb_classes, r.sb_classes.len());
+        if r.is_empty() {
+            assert!(space.bits_per_bit.is_nan());
+            return;
+        }
+        let bits = r.heap_size_bytes() as f64 * 8.0;
+        assert_eq!(space.bits_per_bit, bits / r.len() as f64);
+
+        let (n, ones) = (r.len() as f64, r.count_ones() as f64);
+        let p = ones / n;
+        let h0 = if p == 0.0 || p == 1.0 {
+            0.0
+        } else {
+            -p * p.log2() - (1.0 - p) * (1.0 - p).log2()
+        };
+        let expected = bits / (n * h0);
+        let actual = space.bits_per_entropy_bit;
+        assert!(
+            actual == expected || (actual - expected).abs() < 1e-9 * expected,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+}
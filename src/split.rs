@@ -0,0 +1,284 @@
+//! Splitting and concatenating `RsDict`s.
+//!
+//! Both operations assemble the compressed blocks of their results and then
+//! rebuild the large blocks and select indices from the classes, like loading
+//! serialized bytes.  When a range of the source starts on a small block
+//! boundary and the output so far is a whole number of small blocks, the
+//! codes are copied without decoding them, and only the range's final block
+//! is re-encoded.  Otherwise every block is decoded and shifted into place.
+use super::constants::SMALL_BLOCK_SIZE;
+use super::enum_code;
+use super::{LastBlock, RsDict, VarintBuffer};
+use alloc::vec::Vec;
+use core::mem;
+
+// The compressed blocks of a dictionary being assembled, without its indices.
+struct Blocks {
+    sb_classes: Vec<u8>,
+    sb_indices: VarintBuffer,
+    last_block: LastBlock,
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Self {
+            sb_classes: Vec::new(),
+            sb_indices: VarintBuffer::with_capacity(0),
+            last_block: LastBlock::new(),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.sb_classes.len() as u64 * SMALL_BLOCK_SIZE + self.last_block_len()
+    }
+
+    #[inline]
+    fn last_block_len(&self) -> u64 {
+        self.last_block.num_ones + self.last_block.num_zeros
+    }
+
+    // Encode the full last block and start a new, empty one.
+    fn flush(&mut self) {
+        debug_assert_eq!(self.last_block_len(), SMALL_BLOCK_SIZE);
+        let block = mem::replace(&mut self.last_block, LastBlock::new());
+        let sb_class = block.num_ones as u8;
+        let (code_len, code) = enum_code::encode(block.bits, sb_class);
+        self.sb_classes.push(sb_class);
+        self.sb_indices.push(code_len as usize, code);
+    }
+
+    // Append the low `num_bits` bits of `bits`, whose other bits must be zero.
+    fn push_bits(&mut self, bits: u64, num_bits: u64) {
+        debug_assert!(num_bits > 0 && num_bits <= SMALL_BLOCK_SIZE);
+        debug_assert_eq!(mask(bits, num_bits), bits);
+        if self.last_block_len() == SMALL_BLOCK_SIZE {
+            self.flush();
+        }
+        let offset = self.last_block_len();
+        let fits = num_bits.min(SMALL_BLOCK_SIZE - offset);
+        let low = mask(bits, fits);
+        self.last_block.bits |= low << offset;
+        self.last_block.num_ones += low.count_ones() as u64;
+        self.last_block.num_zeros += fits - low.count_ones() as u64;
+        if fits < num_bits {
+            self.flush();
+            let high = bits >> fits;
+            self.last_block.bits = high;
+            self.last_block.num_ones = high.count_ones() as u64;
+            self.last_block.num_zeros = num_bits - fits - self.last_block.num_ones;
+        }
+    }
+
+    // Append `len` bits of codes starting at `start` in `codes`.
+    fn copy_codes(&mut self, codes: &VarintBuffer, start: u64, len: u64) {
+        let (mut pos, end) = (start as usize, (start + len) as usize);
+        while pos < end {
+            let num_bits = (end - pos).min(64);
+            self.sb_indices.push(num_bits, codes.get(pos, num_bits));
+            pos += num_bits;
+        }
+    }
+
+    fn build<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>(
+        self,
+        select1_index: bool,
+        select0_index: bool,
+    ) -> RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE> {
+        RsDict::from_parts(
+            self.sb_classes,
+            self.sb_indices,
+            self.last_block,
+            select1_index,
+            select0_index,
+        )
+    }
+}
+
+// Keep the low `num_bits` bits of `bits`.
+#[inline]
+fn mask(bits: u64, num_bits: u64) -> u64 {
+    if num_bits >= 64 {
+        bits
+    } else {
+        bits & ((1 << num_bits) - 1)
+    }
+}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE>
+{
+    /// Split the dictionary in two at `pos`, keeping the bits before `pos` and returning a new
+    /// dictionary with the bits from `pos` on.  Both keep this dictionary's select index
+    /// settings.  Panics if `pos > len`.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// let mut r = RsDict::from_blocks([0xdead_beef, u64::MAX, 0xf00d].iter().copied());
+    /// let tail = r.split_off(100);
+    /// assert_eq!((r.len(), tail.len()), (100, 92));
+    /// assert_eq!(tail.rank(50, true), 35);
+    /// ```
+    pub fn split_off(&mut self, pos: u64) -> Self {
+        if pos > self.len {
+            panic!("Out of bounds split position: {} > {}", pos, self.len);
+        }
+        let mut tail = Blocks::new();
+        self.append_range(&mut tail, pos, self.len);
+        let tail = tail.build(self.select1_index, self.select0_index);
+
+        // Truncate in place, decoding only the new last block.
+        let mut head = Blocks::new();
+        if pos > 0 {
+            let last_sblock = ((pos - 1) / SMALL_BLOCK_SIZE) as usize;
+            let last_len = pos - last_sblock as u64 * SMALL_BLOCK_SIZE;
+            let bits = mask(self.blocks_from(last_sblock).next().unwrap(), last_len);
+            let pointer = self.sb_pointer(last_sblock);
+
+            head.sb_classes = mem::take(&mut self.sb_classes);
+            head.sb_classes.truncate(last_sblock);
+            head.sb_indices = mem::replace(&mut self.sb_indices, VarintBuffer::with_capacity(0));
+            head.sb_indices.truncate(pointer as usize);
+            head.last_block.bits = bits;
+            head.last_block.num_ones = bits.count_ones() as u64;
+            head.last_block.num_zeros = last_len - head.last_block.num_ones;
+        }
+        *self = head.build(self.select1_index, self.select0_index);
+        tail
+    }
+
+    /// Concatenate the bits of `dicts` into a new dictionary.  It has the same select index
+    /// settings as the first dictionary, or both indices if there are none.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// let a = RsDict::from_blocks([0b1011].iter().copied());
+    /// let mut b = RsDict::new();
+    /// b.push(true);
+    /// let r = RsDict::concat([&a, &b, &a]);
+    /// assert_eq!(r.len(), 129);
+    /// assert_eq!(r.select1(3), Some(64));
+    /// assert_eq!(r.select1(4), Some(65));
+    /// ```
+    pub fn concat<'a>(dicts: impl IntoIterator<Item = &'a Self>) -> Self {
+        let mut dicts = dicts.into_iter().peekable();
+        let (select1_index, select0_index) = match dicts.peek() {
+            Some(first) => (first.select1_index, first.select0_index),
+            None => (true, true),
+        };
+        let mut out = Blocks::new();
+        for dict in dicts {
+            dict.append_range(&mut out, 0, dict.len);
+        }
+        out.build(select1_index, select0_index)
+    }
+
+    // Append the bits in `start..end` to `out`.
+    fn append_range(&self, out: &mut Blocks, start: u64, end: u64) {
+        if start == end {
+            return;
+        }
+        let mut sblock = (start / SMALL_BLOCK_SIZE) as usize;
+        let last_sblock = ((end - 1) / SMALL_BLOCK_SIZE) as usize;
+
+        // Copy whole blocks before the last one without decoding them.
+        if start % SMALL_BLOCK_SIZE == 0
+            && out.len() % SMALL_BLOCK_SIZE == 0
+            && sblock < last_sblock
+        {
+            if out.len() > 0 {
+                out.flush();
+            }
+            let pointer = self.sb_pointer(sblock);
+            let end_pointer = self.sb_pointer(last_sblock);
+            out.sb_classes
+                .extend_from_slice(&self.sb_classes[sblock..last_sblock]);
+            out.copy_codes(&self.sb_indices, pointer, end_pointer - pointer);
+            sblock = last_sblock;
+        }
+
+        let blocks = self.blocks_from(sblock).take(last_sblock - sblock + 1);
+        for (i, block) in blocks.enumerate() {
+            let block_start = (sblock + i) as u64 * SMALL_BLOCK_SIZE;
+            let lo = start.saturating_sub(block_start);
+            let hi = (end - block_start).min(SMALL_BLOCK_SIZE);
+            out.push_bits(mask(block >> lo, hi - lo), hi - lo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::test_block;
+    use crate::{RsDict, RsDictBuilder};
+    use alloc::vec::Vec;
+
+    fn bits_of<const L: u64, const S: u64>(r: &RsDict<L, S>) -> Vec<bool> {
+        (0..r.len() as u64).map(|i| r.get_bit(i)).collect()
+    }
+
+    fn test_dict(blocks: Vec<u64>, tail: Vec<bool>) -> RsDict<128, 256> {
+        let mut r = RsDict::<128, 256>::from_blocks_with_block_sizes(blocks.into_iter().map(|b| {
+            match b % 3 {
+                0 => test_block(b, false),
+                1 => test_block(b, true),
+                _ => 0,
+            }
+        }));
+        for bit in tail {
+            r.push(bit);
+        }
+        r
+    }
+
+    #[quickcheck]
+    fn qc_split_off(blocks: Vec<u64>, tail: Vec<bool>, pos: u64, aligned: bool) {
+        let mut r = test_dict(blocks, tail);
+        let bits = bits_of(&r);
+        let mut pos = pos % (r.len() as u64 + 1);
+        if aligned {
+            pos -= pos % 64;
+        }
+        let original = r.clone();
+
+        let suffix = r.split_off(pos);
+        assert_eq!(r.validate(), Ok(()));
+        assert_eq!(suffix.validate(), Ok(()));
+        assert_eq!(bits_of(&r), &bits[..pos as usize]);
+        assert_eq!(bits_of(&suffix), &bits[pos as usize..]);
+        assert_eq!(r, bits[..pos as usize].iter().copied().collect());
+
+        assert_eq!(RsDict::concat([&r, &suffix]), original);
+    }
+
+    #[quickcheck]
+    fn qc_concat(pieces: Vec<(Vec<u64>, Vec<bool>)>) {
+        let dicts: Vec<RsDict<128, 256>> = pieces
+            .into_iter()
+            .map(|(blocks, tail)| test_dict(blocks, tail))
+            .collect();
+        let bits: Vec<bool> = dicts.iter().flat_map(bits_of).collect();
+
+        let r = RsDict::concat(&dicts);
+        assert_eq!(r.validate(), Ok(()));
+        assert_eq!(bits_of(&r), bits);
+        assert_eq!(r, bits.iter().copied().collect());
+    }
+
+    #[test]
+    fn test_concat_indices() {
+        let r = RsDictBuilder::<128, 256>::with_block_sizes()
+            .select1_index(false)
+            .from_blocks([1, 2, 3].iter().copied());
+        let joined = RsDict::concat([&r, &RsDict::with_block_sizes()]);
+        assert_eq!(joined.validate(), Ok(()));
+        assert!(joined.select_one_inds.is_empty());
+        assert!(!joined.select_zero_inds.is_empty());
+
+        let empty = RsDict::<128, 256>::concat([]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.validate(), Ok(()));
+    }
+}
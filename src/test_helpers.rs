@@ -9,3 +9,45 @@ pub fn hash_u64(x: u64) -> u64 {
     h.write_u64(x);
     h.finish()
 }
+
+// Hash `seed` into a block that's uniformly random, or if `sparse`, has about
+// one bit in eight set, so its enumerative code is short instead of raw.
+pub fn test_block(seed: u64, sparse: bool) -> u64 {
+    let b = hash_u64(seed);
+    if sparse {
+        b & (b >> 7) & (b >> 13)
+    } else {
+        b
+    }
+}
+
+// Check every query on `$r` against a naive scan over `$bits`.  It's a macro
+// so it can call each dictionary's own inherent methods.
+macro_rules! check_rank_select {
+    ($r:expr, $bits:expr $(,)?) => {{
+        let (r, bits): (_, &[bool]) = ($r, $bits);
+        assert_eq!(r.len(), bits.len());
+        let (mut one_rank, mut zero_rank) = (0, 0);
+        for (i, &bit) in bits.iter().enumerate() {
+            let i = i as u64;
+            assert_eq!(r.get_bit(i), bit);
+            assert_eq!($crate::BitRank::bit_and_one_rank(r, i), (bit, one_rank));
+            assert_eq!(r.rank(i, true), one_rank);
+            assert_eq!(r.rank(i, false), zero_rank);
+            if bit {
+                assert_eq!(r.select1(one_rank), Some(i));
+                assert_eq!(r.select(one_rank, true), Some(i));
+                one_rank += 1;
+            } else {
+                assert_eq!(r.select0(zero_rank), Some(i));
+                assert_eq!(r.select(zero_rank, false), Some(i));
+                zero_rank += 1;
+            }
+        }
+        assert_eq!(r.count_ones() as u64, one_rank);
+        assert_eq!(r.count_zeros() as u64, zero_rank);
+        assert_eq!(r.select1(one_rank), None);
+        assert_eq!(r.select0(zero_rank), None);
+    }};
+}
+pub(crate) use check_rank_select;
@@ -0,0 +1,326 @@
+//! Consistency checks for `RsDict`'s internal invariants.
+use super::{RsDict, SmallBlock};
+use core::fmt;
+
+/// The first inconsistency [`RsDict::validate`] found in a dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// `len` isn't the sum of the number of ones and zeros.
+    Length {
+        /// Number of bits in the dictionary.
+        len: u64,
+        /// Number of ones counted while building the dictionary.
+        num_ones: u64,
+        /// Number of zeros counted while building the dictionary.
+        num_zeros: u64,
+    },
+    /// The last block's counts don't match its bits or the dictionary's length.
+    LastBlock,
+    /// There isn't a class for every small block before the last one.
+    SmallBlockCount {
+        /// Number of small blocks before the last one.
+        expected: usize,
+        /// Number of classes.
+        actual: usize,
+    },
+    /// A small block's class is more than the number of bits in a small block.
+    Class {
+        /// Index of the small block.
+        block: usize,
+        /// The block's class.
+        class: u8,
+    },
+    /// `sb_classes` doesn't have the padding that rank's vectorized scan reads into.
+    Padding,
+    /// The total length of the enumerative codes doesn't match the classes.
+    CodeLength {
+        /// Sum of the code lengths of every class.
+        expected: u64,
+        /// Number of bits in the code buffer.
+        actual: u64,
+    },
+    /// The code buffer has bits set past the end of the codes, which equality and hashing read.
+    CodePadding,
+    /// A small block's code doesn't encode a block of its class.
+    Code {
+        /// Index of the small block.
+        block: usize,
+    },
+    /// The number of ones doesn't match the sum of the classes.
+    Ones {
+        /// Number of ones in the classes and last block.
+        expected: u64,
+        /// Number of ones counted while building the dictionary.
+        actual: u64,
+    },
+    /// A large block's rank or code pointer is wrong, or there are too many or too few of them.
+    LargeBlock {
+        /// Index of the first large block that doesn't match.
+        index: usize,
+    },
+    /// The select index for `bit` doesn't match the bitmap.
+    SelectIndex {
+        /// Which select index is wrong.
+        bit: bool,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValidationError::Length {
+                len,
+                num_ones,
+                num_zeros,
+            } => write!(
+                f,
+                "length {} isn't {} ones plus {} zeros",
+                len, num_ones, num_zeros
+            ),
+            ValidationError::LastBlock => write!(f, "last block is inconsistent"),
+            ValidationError::SmallBlockCount { expected, actual } => write!(
+                f,
+                "expected {} small block classes, found {}",
+                expected, actual
+            ),
+            ValidationError::Class { block, class } => {
+                write!(f, "small block {} has invalid class {}", block, class)
+            }
+            ValidationError::Padding => write!(f, "small block classes are missing padding"),
+            ValidationError::CodeLength { expected, actual } => {
+                write!(f, "expected {} bits of codes, found {}", expected, actual)
+            }
+            ValidationError::CodePadding => write!(f, "code buffer has bits set past its end"),
+            ValidationError::Code { block } => {
+                write!(
+                    f,
+                    "small block {} has a code that doesn't match its class",
+                    block
+                )
+            }
+            ValidationError::Ones { expected, actual } => {
+                write!(f, "expected {} ones, found {}", expected, actual)
+            }
+            ValidationError::LargeBlock { index } => {
+                write!(f, "large block {} is inconsistent", index)
+            }
+            ValidationError::SelectIndex { bit } => {
+                write!(f, "select index for {} is inconsistent", bit as u8)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {}
+
+impl<const LARGE_BLOCK_SIZE: u64, const SELECT_BLOCK_SIZE: u64, C: SmallBlock>
+    RsDict<LARGE_BLOCK_SIZE, SELECT_BLOCK_SIZE, C>
+{
+    /// Check the dictionary's internal invariants, returning the first inconsistency found.  This
+    /// decodes every small block, so it's meant for debugging and for checking dictionaries from
+    /// untrusted sources rather than for hot paths.
+    ///
+    /// ```
+    /// use rsdict::RsDict;
+    ///
+    /// let mut r = RsDict::from_blocks([0xdead_beef, u64::MAX].iter().copied());
+    /// r.push(true);
+    /// assert_eq!(r.validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.len != self.num_ones + self.num_zeros {
+            return Err(ValidationError::Length {
+                len: self.len,
+                num_ones: self.num_ones,
+                num_zeros: self.num_zeros,
+            });
+        }
+
+        let num_sb = if self.len > 0 {
+            ((self.len - 1) / C::BITS) as usize
+        } else {
+            0
+        };
+        let last_len = self.len - num_sb as u64 * C::BITS;
+        let last_block = &self.last_block;
+        if last_block.num_ones != last_block.bits.ones()
+            || last_block.num_ones + last_block.num_zeros != last_len
+            || (last_len < C::BITS && last_block.bits.count_suffix(last_len) != 0)
+        {
+            return Err(ValidationError::LastBlock);
+        }
+
+        if self.sb_classes.len() != num_sb {
+            return Err(ValidationError::SmallBlockCount {
+                expected: num_sb,
+                actual: self.sb_classes.len(),
+            });
+        }
+        let mut code_len = 0;
+        for (block, &class) in self.sb_classes.iter().enumerate() {
+            if class as u64 > C::BITS {
+                return Err(ValidationError::Class { block, class });
+            }
+            code_len += C::code_length(class) as u64;
+        }
+        if self.sb_classes.capacity() < Self::sb_classes_padded_len(num_sb) {
+            return Err(ValidationError::Padding);
+        }
+        let buf_bits = self.sb_indices.buf.len() as u64 * 64;
+        if self.sb_indices.len() as u64 != code_len
+            || buf_bits < code_len
+            || buf_bits >= code_len + 64
+        {
+            return Err(ValidationError::CodeLength {
+                expected: code_len,
+                actual: self.sb_indices.len() as u64,
+            });
+        }
+        if code_len % 64 != 0
+            && self.sb_indices.buf[(code_len / 64) as usize] >> (code_len % 64) != 0
+        {
+            return Err(ValidationError::CodePadding);
+        }
+
+        let mut pointer = 0;
+        for (block, &class) in self.sb_classes.iter().enumerate() {
+            let code_length = C::code_length(class);
+            let code = self.read_sb_index(pointer, code_length);
+            if !code.is_valid(class) {
+                return Err(ValidationError::Code { block });
+            }
+            pointer += code_length as u64;
+        }
+
+        // Now that the classes are consistent, rebuild the indices from them
+        // and compare.
+        let mut expected =
+            Self::with_capacity_and_indices(0, self.select1_index, self.select0_index);
+        expected.rebuild_indices(&self.sb_classes, &self.last_block);
+        if expected.num_ones != self.num_ones {
+            return Err(ValidationError::Ones {
+                expected: expected.num_ones,
+                actual: self.num_ones,
+            });
+        }
+        if expected.large_blocks != self.large_blocks {
+            let index = expected
+                .large_blocks
+                .iter()
+                .zip(&self.large_blocks)
+                .position(|(e, a)| e != a)
+                .unwrap_or_else(|| expected.large_blocks.len().min(self.large_blocks.len()));
+            return Err(ValidationError::LargeBlock { index });
+        }
+        if expected.select_one_inds != self.select_one_inds {
+            return Err(ValidationError::SelectIndex { bit: true });
+        }
+        if expected.select_zero_inds != self.select_zero_inds {
+            return Err(ValidationError::SelectIndex { bit: false });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationError;
+    use crate::test_helpers::test_block;
+    use crate::{RsDict, RsDictBuilder};
+    use alloc::vec::Vec;
+
+    #[quickcheck]
+    fn qc_validate(blocks: Vec<u64>, tail: Vec<bool>, sparse: bool) {
+        let blocks: Vec<u64> = blocks.into_iter().map(|b| test_block(b, sparse)).collect();
+        let mut r = RsDict::<128, 256>::from_blocks_with_block_sizes(blocks.iter().cloned());
+        let mut pushed = RsDictBuilder::<128, 256>::with_block_sizes()
+            .select0_index(false)
+            .build();
+        for block in &blocks {
+            for i in 0..64 {
+                pushed.push((block >> i) & 1 != 0);
+            }
+        }
+        for bit in tail {
+            r.push(bit);
+            pushed.push(bit);
+        }
+        assert_eq!(r.validate(), Ok(()));
+        assert_eq!(pushed.validate(), Ok(()));
+        assert_eq!(r.clone().validate(), Ok(()));
+        let bytes = r.to_bytes();
+        assert_eq!(RsDict::from_bytes(&bytes).unwrap().validate(), Ok(()));
+        r.shrink_to_fit();
+        assert_eq!(r.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let blocks = [0b1011u64, 0, u64::MAX, 1 << 63, 0xdead_beef];
+        let r = RsDict::<128, 256>::from_blocks_with_block_sizes(blocks.iter().cloned());
+        assert_eq!(r.validate(), Ok(()));
+
+        let mut bad = r.clone();
+        bad.num_zeros += 1;
+        assert!(matches!(
+            bad.validate(),
+            Err(ValidationError::Length { .. })
+        ));
+
+        let mut bad = r.clone();
+        bad.last_block.bits ^= 1;
+        assert_eq!(bad.validate(), Err(ValidationError::LastBlock));
+
+        let mut bad = r.clone();
+        bad.sb_classes[1] = 65;
+        assert_eq!(
+            bad.validate(),
+            Err(ValidationError::Class {
+                block: 1,
+                class: 65
+            })
+        );
+
+        let mut bad = r.clone();
+        bad.sb_classes[1] = 1;
+        assert!(matches!(
+            bad.validate(),
+            Err(ValidationError::CodeLength { .. })
+        ));
+
+        let mut bad = r.clone();
+        bad.sb_indices.buf[0] = u64::MAX >> (64 - 16);
+        assert_eq!(bad.validate(), Err(ValidationError::Code { block: 0 }));
+
+        let mut bad = r.clone();
+        *bad.sb_indices.buf.last_mut().unwrap() |= 1 << 63;
+        assert_eq!(bad.validate(), Err(ValidationError::CodePadding));
+
+        let mut bad = r.clone();
+        bad.large_blocks[1].rank += 1;
+        assert_eq!(
+            bad.validate(),
+            Err(ValidationError::LargeBlock { index: 1 })
+        );
+
+        let mut bad = r.clone();
+        bad.large_blocks.pop();
+        assert_eq!(
+            bad.validate(),
+            Err(ValidationError::LargeBlock { index: 2 })
+        );
+
+        let mut bad = r.clone();
+        bad.select_zero_inds.push(0);
+        assert_eq!(
+            bad.validate(),
+            Err(ValidationError::SelectIndex { bit: false })
+        );
+
+        let mut bad = r;
+        bad.sb_classes.shrink_to_fit();
+        assert_eq!(bad.validate(), Err(ValidationError::Padding));
+    }
+}
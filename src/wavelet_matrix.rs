@@ -0,0 +1,198 @@
+use alloc::vec::Vec;
+use super::{BitRank, RsDict};
+use core::iter::FromIterator;
+
+/// Wavelet matrix over a sequence of fixed-width symbols, with one bitmap
+/// dictionary (an `RsDict` by default) per bit level.
+///
+/// Level `l` stores the `l`th most significant bit of every symbol, where the
+/// symbols have been stably partitioned by all of their more significant bits
+/// (zeros first).  Then, `access` and `rank` both take a single rank per
+/// level.  See Claude, Navarro and Ordóñez, "The wavelet matrix" for
+/// details.
+///
+/// Any [`BitRank`] implementation can store the levels, e.g.
+/// `WaveletMatrix::<PlainRsDict>::from_symbols` for symbols whose bits are
+/// close to random.
+#[derive(Debug, Clone)]
+pub struct WaveletMatrix<B = RsDict> {
+    len: u64,
+    bit_width: u32,
+    levels: Vec<B>,
+    // Number of zeros in each level, i.e. where the ones start in the next one.
+    level_zeros: Vec<u64>,
+}
+
+impl WaveletMatrix {
+    /// Build a wavelet matrix from `symbols`, each of which must fit in `bit_width` bits.  Panics
+    /// if any symbol doesn't.
+    #[inline]
+    pub fn new(symbols: &[u64], bit_width: u32) -> Self {
+        Self::from_symbols(symbols, bit_width)
+    }
+}
+
+impl<B: BitRank> WaveletMatrix<B> {
+    /// Same as [`WaveletMatrix::new`] but storing the levels in `B`.
+    pub fn from_symbols(symbols: &[u64], bit_width: u32) -> Self
+    where
+        B: FromIterator<bool>,
+    {
+        assert!(
+            0 < bit_width && bit_width <= 64,
+            "Invalid bit width: {}",
+            bit_width
+        );
+        assert!(
+            bit_width == 64 || symbols.iter().all(|&s| s >> bit_width == 0),
+            "Symbol doesn't fit in {} bits",
+            bit_width
+        );
+        let mut levels = Vec::with_capacity(bit_width as usize);
+        let mut level_zeros = Vec::with_capacity(bit_width as usize);
+
+        let mut current = symbols.to_vec();
+        let mut zeros = Vec::with_capacity(symbols.len());
+        let mut ones = Vec::with_capacity(symbols.len());
+
+        for level in 0..bit_width {
+            let shift = bit_width - level - 1;
+            levels.push(current.iter().map(|&s| (s >> shift) & 1 != 0).collect());
+
+            zeros.clear();
+            ones.clear();
+            for &s in &current {
+                if (s >> shift) & 1 != 0 {
+                    ones.push(s);
+                } else {
+                    zeros.push(s);
+                }
+            }
+            level_zeros.push(zeros.len() as u64);
+            current.clear();
+            current.extend_from_slice(&zeros);
+            current.extend_from_slice(&ones);
+        }
+
+        Self {
+            len: symbols.len() as u64,
+            bit_width,
+            levels,
+            level_zeros,
+        }
+    }
+
+    /// Return the number of symbols in the sequence.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return whether the sequence is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the number of bits used per symbol.
+    #[inline]
+    pub fn bit_width(&self) -> u32 {
+        self.bit_width
+    }
+
+    /// Return the symbol at `pos`.  Panics if `pos` is out-of-bounds.
+    pub fn access(&self, mut pos: u64) -> u64 {
+        if pos >= self.len {
+            panic!("Out of bounds position: {} >= {}", pos, self.len);
+        }
+        let mut symbol = 0;
+        for (level, &zeros) in self.levels.iter().zip(&self.level_zeros) {
+            let (bit, one_rank) = level.bit_and_one_rank(pos);
+            symbol <<= 1;
+            if bit {
+                symbol |= 1;
+                pos = zeros + one_rank;
+            } else {
+                pos -= one_rank;
+            }
+        }
+        symbol
+    }
+
+    /// Count the number of occurrences of `symbol` left of `pos`.  Unlike
+    /// `BitRank::rank`, `pos` may be equal to `len()`.
+    pub fn rank(&self, symbol: u64, pos: u64) -> u64 {
+        if pos > self.len {
+            panic!("Out of bounds position: {} > {}", pos, self.len);
+        }
+        let (mut start, mut end) = (0, pos);
+        for (i, (level, &zeros)) in self.levels.iter().zip(&self.level_zeros).enumerate() {
+            let shift = self.bit_width - i as u32 - 1;
+            if (symbol >> shift) & 1 != 0 {
+                start = zeros + rank_to(level, start, true);
+                end = zeros + rank_to(level, end, true);
+            } else {
+                start = rank_to(level, start, false);
+                end = rank_to(level, end, false);
+            }
+        }
+        end - start
+    }
+}
+
+// `BitRank::rank` with `pos == len` allowed.
+#[inline]
+fn rank_to<B: BitRank>(r: &B, pos: u64, bit: bool) -> u64 {
+    if pos == r.len() as u64 {
+        if bit {
+            r.count_ones() as u64
+        } else {
+            r.count_zeros() as u64
+        }
+    } else {
+        r.rank(pos, bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use super::WaveletMatrix;
+    use crate::test_helpers::hash_u64;
+    use crate::PlainRsDict;
+
+    #[quickcheck]
+    fn qc_access_and_rank(seeds: Vec<u64>, bit_width: u8) {
+        let bit_width = (bit_width % 12) as u32 + 1;
+        let symbols: Vec<u64> = seeds
+            .iter()
+            .flat_map(|&s| (0..8).map(move |i| hash_u64(s.wrapping_add(i))))
+            .map(|s| s & ((1 << bit_width) - 1))
+            .collect();
+        let wm = WaveletMatrix::new(&symbols, bit_width);
+        assert_eq!(wm.len(), symbols.len());
+
+        let plain = WaveletMatrix::<PlainRsDict>::from_symbols(&symbols, bit_width);
+        for (i, &s) in symbols.iter().enumerate() {
+            assert_eq!(wm.access(i as u64), s);
+            assert_eq!(plain.access(i as u64), s);
+            assert_eq!(plain.rank(s, i as u64), wm.rank(s, i as u64));
+        }
+        for &s in symbols.iter().take(4) {
+            let mut expected = 0;
+            for (i, &t) in symbols.iter().enumerate() {
+                assert_eq!(wm.rank(s, i as u64), expected);
+                if t == s {
+                    expected += 1;
+                }
+            }
+            assert_eq!(wm.rank(s, symbols.len() as u64), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_symbol_too_wide() {
+        WaveletMatrix::new(&[1, 2, 4], 2);
+    }
+}
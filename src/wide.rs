@@ -0,0 +1,304 @@
+//! Variant of `RsDict` with 128-bit small blocks.
+//!
+//! Every small block pays a byte in `sb_classes` on top of its enumerative
+//! code, which is a 12.5% overhead with 64-bit blocks.  Doubling the small
+//! block size halves that overhead, and since `ceil(log2(C(128, k)))` is less
+//! than twice `ceil(log2(C(64, k / 2)))`, the codes themselves get closer to
+//! the entropy of the bitmap too.  The cost is that decoding a small block
+//! walks twice as many bits with `u128` arithmetic, so `rank` and `select`
+//! are slower than with `RsDict`.
+//!
+//! `WideRsDict` wraps `RsDict` with `u128` small blocks and the default
+//! large block and select sampling sizes, giving it constructors that don't
+//! need the block sizes spelled out.
+use super::constants::{DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE};
+use super::iter::RsDictIterator;
+use super::{from_padded_blocks, FromBytesError, RsDict, SpaceBreakdown, ValidationError};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Rank and select dictionary with 128-bit small blocks, trading some query
+/// speed for space on dense-ish bitmaps.  Use it in place of `RsDict` to pick
+/// the wider representation when building the dictionary.
+///
+/// ```
+/// use rsdict::WideRsDict;
+///
+/// let blocks = vec![0b1011, 0, 0b110];
+/// let mut r = WideRsDict::from_blocks(blocks.into_iter());
+/// r.push(true);
+/// assert_eq!(r.len(), 193);
+/// assert_eq!(r.rank(130, true), 4);
+/// assert_eq!(r.select1(3), Some(129));
+/// assert_eq!(r.select1(5), Some(192));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WideRsDict(RsDict<DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, u128>);
+
+impl WideRsDict {
+    /// Create a dictionary from a bitset, specified as an iterator of 64-bit
+    /// blocks.  Pairs of blocks are combined into a single small block, low
+    /// block first.
+    #[inline]
+    pub fn from_blocks(blocks: impl Iterator<Item = u64>) -> Self {
+        Self(RsDict::from_blocks_with_block_sizes(blocks))
+    }
+
+    /// Create a new `WideRsDict` with zero capacity.
+    #[inline]
+    pub fn new() -> Self {
+        Self(RsDict::with_block_sizes())
+    }
+
+    /// Create a new `WideRsDict` with room for `n` bits preallocated.
+    #[inline]
+    pub fn with_capacity(n: usize) -> Self {
+        Self(RsDict::with_capacity_and_block_sizes(n))
+    }
+
+    /// Return the number of bits the dictionary can hold without reallocating its small block
+    /// classes or its indices.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Reserve room for pushing at least `additional_bits` more bits without reallocating, so
+    /// [`WideRsDict::capacity`] is at least `len() + additional_bits` afterwards.
+    #[inline]
+    pub fn reserve(&mut self, additional_bits: usize) {
+        self.0.reserve(additional_bits)
+    }
+
+    /// Shrink the dictionary's allocations to fit its contents.
+    #[inline]
+    pub fn shrink_to_fit(&mut self) {
+        self.0.shrink_to_fit()
+    }
+
+    /// Deserialize a dictionary written by [`WideRsDict::to_bytes`] or [`RsDict::to_bytes`],
+    /// re-encoding its blocks as 128-bit ones.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError> {
+        let r = RsDict::from_bytes(bytes)?;
+        Ok(Self(from_padded_blocks(r.blocks(), r.len())))
+    }
+
+    /// Serialize the dictionary into the same format as [`RsDict::to_bytes`], so either type
+    /// can read the other's bytes.  The format stores 64-bit blocks, so this re-encodes every
+    /// small block.
+    ///
+    /// ```
+    /// use rsdict::{RsDict, WideRsDict};
+    ///
+    /// let blocks = [0xdead_beef, 0, u64::MAX];
+    /// let wide = WideRsDict::from_blocks(blocks.iter().copied());
+    /// let r = RsDict::from_bytes(&wide.to_bytes()).unwrap();
+    /// assert_eq!(r, RsDict::from_blocks(blocks.iter().copied()));
+    /// assert_eq!(WideRsDict::from_bytes(&r.to_bytes()), Ok(wide));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_narrow().to_bytes()
+    }
+
+    /// Return the [`RsDict::fingerprint`] of an `RsDict` with the same bits, which re-encodes
+    /// every small block.
+    pub fn fingerprint(&self) -> u64 {
+        self.to_narrow().fingerprint()
+    }
+
+    // Re-encode the bitmap with 64-bit small blocks.
+    fn to_narrow(&self) -> RsDict {
+        from_padded_blocks(self.0.blocks(), self.len())
+    }
+
+    /// Check the dictionary's internal invariants, returning the first inconsistency found.
+    #[inline]
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.0.validate()
+    }
+
+    /// Return the number of bytes the dictionary has allocated on the heap, including unused
+    /// capacity.
+    #[inline]
+    pub fn heap_size_bytes(&self) -> usize {
+        self.0.heap_size_bytes()
+    }
+
+    /// Break down [`WideRsDict::heap_size_bytes`] by field and compare it to the size of the
+    /// bitmap.
+    #[inline]
+    pub fn space_breakdown(&self) -> SpaceBreakdown {
+        self.0.space_breakdown()
+    }
+
+    /// Non-inclusive rank: Count the number of `bit` values left of `pos`. Panics if `pos` is
+    /// out-of-bounds.
+    #[inline]
+    pub fn rank(&self, pos: u64, bit: bool) -> u64 {
+        self.0.rank(pos, bit)
+    }
+
+    /// Prefetch the memory `rank(pos, _)` reads.  See [`RsDict::prefetch_rank`].
+    #[inline]
+    pub fn prefetch_rank(&self, pos: u64) {
+        self.0.prefetch_rank(pos)
+    }
+
+    /// Compute `rank(pos, bit)` for each of `positions`.  See [`RsDict::rank_many`].
+    #[inline]
+    pub fn rank_many(&self, positions: &[u64], bit: bool) -> Vec<u64> {
+        self.0.rank_many(positions, bit)
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap along with the number of
+    /// ones left of it.
+    #[inline]
+    pub fn bit_and_one_rank(&self, pos: u64) -> (bool, u64) {
+        self.0.bit_and_one_rank(pos)
+    }
+
+    /// Inclusive rank: Count the number of `bit` values at indices less than or equal to
+    /// `pos`. Panics if `pos` is out-of-bounds.
+    #[inline]
+    pub fn inclusive_rank(&self, pos: u64, bit: bool) -> u64 {
+        self.0.inclusive_rank(pos, bit)
+    }
+
+    /// Query the `pos`th bit (zero-indexed) of the underlying bitmap.
+    #[inline]
+    pub fn get_bit(&self, pos: u64) -> bool {
+        self.0.get_bit(pos)
+    }
+
+    /// Compute the position of the `rank`th instance of `bit` (zero-indexed), returning `None` if
+    /// there are not `rank + 1` instances of `bit` in the array.
+    #[inline]
+    pub fn select(&self, rank: u64, bit: bool) -> Option<u64> {
+        self.0.select(rank, bit)
+    }
+
+    /// Specialized version of [`WideRsDict::select`] for finding positions of zeros.
+    #[inline]
+    pub fn select0(&self, rank: u64) -> Option<u64> {
+        self.0.select0(rank)
+    }
+
+    /// Specialized version of [`WideRsDict::select`] for finding positions of ones.
+    #[inline]
+    pub fn select1(&self, rank: u64) -> Option<u64> {
+        self.0.select1(rank)
+    }
+
+    /// Return the length of the underlying bitmap.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return whether the underlying bitmap is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Count the number of set bits in the underlying bitmap.
+    #[inline]
+    pub fn count_ones(&self) -> usize {
+        self.0.count_ones()
+    }
+
+    /// Count the number of unset bits in the underlying bitmap.
+    #[inline]
+    pub fn count_zeros(&self) -> usize {
+        self.0.count_zeros()
+    }
+
+    /// Push a bit at the end of the underlying bitmap.
+    #[inline]
+    pub fn push(&mut self, bit: bool) {
+        self.0.push(bit)
+    }
+
+    /// Return an iterator over the indices of the bits set to one.  Each small block is decoded
+    /// once, as the iterator reaches it.
+    #[inline]
+    pub fn iter(
+        &self,
+    ) -> RsDictIterator<'_, DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, u128> {
+        self.0.iter()
+    }
+
+    /// Return an iterator over the indices of the bits set to one within `range`.
+    #[inline]
+    pub fn iter_in_range(
+        &self,
+        range: Range<u64>,
+    ) -> RsDictIterator<'_, DEFAULT_LARGE_BLOCK_SIZE, DEFAULT_SELECT_BLOCK_SIZE, u128> {
+        self.0.iter_in_range(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use super::WideRsDict;
+    use crate::test_helpers::{check_rank_select, hash_u64, test_block};
+    use crate::RsDict;
+
+    #[quickcheck]
+    fn qc_wide_rsdict(blocks: Vec<u64>, tail: Vec<bool>) {
+        // Thin out some of the blocks so we get short codes too.
+        let blocks: Vec<u64> = blocks
+            .into_iter()
+            .map(|b| match b % 4 {
+                0 => test_block(b, false),
+                1 => test_block(b, true),
+                2 => 0,
+                _ => b,
+            })
+            .collect();
+        let mut bits = vec![];
+        for &block in &blocks {
+            bits.extend((0..64).map(|i| (block >> i) & 1 != 0));
+        }
+        let mut r = WideRsDict::from_blocks(blocks.into_iter());
+        for &bit in &tail {
+            r.push(bit);
+            bits.push(bit);
+        }
+        check_rank_select!(&r, &bits);
+
+        let ones: Vec<u64> = (0..bits.len() as u64)
+            .filter(|&i| bits[i as usize])
+            .collect();
+        assert_eq!(r.iter().collect::<Vec<_>>(), ones);
+        let mid = bits.len() as u64 / 3;
+        assert_eq!(
+            r.iter_in_range(mid..2 * mid).collect::<Vec<_>>(),
+            ones.iter()
+                .copied()
+                .filter(|i| (mid..2 * mid).contains(i))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(r.validate(), Ok(()));
+
+        let narrow: RsDict = bits.iter().copied().collect();
+        assert_eq!(r.fingerprint(), narrow.fingerprint());
+        assert_eq!(r.to_bytes(), narrow.to_bytes());
+        assert_eq!(WideRsDict::from_bytes(&narrow.to_bytes()), Ok(r));
+    }
+
+    #[test]
+    fn test_smaller_than_rsdict() {
+        // About one bit in eight set.
+        let blocks: Vec<u64> = (0..4096)
+            .map(|i| hash_u64(i) & hash_u64(i + 4096) & hash_u64(i + 8192))
+            .collect();
+        let narrow = RsDict::from_blocks(blocks.iter().cloned());
+        let wide = WideRsDict::from_blocks(blocks.iter().cloned());
+        let narrow_bits = narrow.sb_classes.len() * 8 + narrow.sb_indices.len();
+        let wide_bits = wide.0.sb_classes.len() * 8 + wide.0.sb_indices.len();
+        assert!(wide_bits < narrow_bits, "{} >= {}", wide_bits, narrow_bits);
+    }
+}
@@ -0,0 +1,149 @@
+// Build the crate as a static library and run the C test program in
+// `tests/capi/test.c` against it.  Needs a C compiler, found with `$CC` or
+// else `cc`.  Also check that `include/rsdict.h` declares exactly the
+// functions that `src/capi.rs` exports.
+#![cfg(feature = "capi")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Translate a Rust parameter or return type from `src/capi.rs` to C.
+fn c_type(ty: &str) -> String {
+    let (prefix, base) = if let Some(base) = ty.strip_prefix("*const ") {
+        ("const ", base)
+    } else if let Some(base) = ty.strip_prefix("*mut ") {
+        ("", base)
+    } else {
+        ("", ty)
+    };
+    let base = match base {
+        "rsdict_t" | "bool" => base,
+        "u8" => "uint8_t",
+        "u64" => "uint64_t",
+        "usize" => "size_t",
+        _ => panic!("No C type for {}", ty),
+    };
+    let pointer = if ty.starts_with('*') { " *" } else { " " };
+    format!("{}{}{}", prefix, base, pointer)
+}
+
+// Generate the C prototypes of the `extern "C"` functions in `src/capi.rs`.
+fn generate_prototypes(source: &str) -> Vec<String> {
+    source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|item| {
+            let signature = item[..item.find('{').unwrap()].split_whitespace();
+            let signature = signature.collect::<Vec<_>>().join(" ");
+            let (name, rest) = signature.split_at(signature.find('(').unwrap());
+            let (params, ret) = rest.split_at(rest.rfind(')').unwrap() + 1);
+            let params: Vec<String> = params[1..params.len() - 1]
+                .split(',')
+                .map(str::trim)
+                .filter(|param| !param.is_empty())
+                .map(|param| {
+                    let (name, ty) = param.split_at(param.find(':').unwrap());
+                    format!("{}{}", c_type(ty[1..].trim()), name)
+                })
+                .collect();
+            let params = if params.is_empty() {
+                "void".to_string()
+            } else {
+                params.join(", ")
+            };
+            let ret = match ret.trim().strip_prefix("->") {
+                Some(ty) => c_type(ty.trim()),
+                None => "void ".to_string(),
+            };
+            format!("{}{}({});", ret, name, params)
+        })
+        .collect()
+}
+
+// Extract the function prototypes from a C header, skipping comments and
+// preprocessor lines.
+fn header_prototypes(header: &str) -> Vec<String> {
+    let mut code = String::new();
+    let mut rest = header;
+    while let Some(start) = rest.find("/*") {
+        code.push_str(&rest[..start]);
+        let end = rest[start..].find("*/").expect("Unterminated comment");
+        rest = &rest[start + end + 2..];
+    }
+    code.push_str(rest);
+    code.lines()
+        .map(str::trim)
+        .filter(|line| line.ends_with(");") && !line.starts_with('#'))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect()
+}
+
+#[test]
+fn test_header_matches_source() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = fs::read_to_string(root.join("src/capi.rs")).unwrap();
+    let header = fs::read_to_string(root.join("include/rsdict.h")).unwrap();
+    let expected = generate_prototypes(&source);
+    assert!(!expected.is_empty());
+    assert_eq!(
+        header_prototypes(&header),
+        expected,
+        "include/rsdict.h is out of sync with src/capi.rs"
+    );
+}
+
+#[test]
+fn test_capi() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capi");
+
+    // Use a separate target directory so we don't wait on the lock for the
+    // build running this test.
+    let output = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .current_dir(root)
+        .args([
+            "rustc",
+            "--lib",
+            "--features",
+            "capi",
+            "--crate-type",
+            "staticlib",
+        ])
+        .arg("--target-dir")
+        .arg(&out_dir)
+        .args(["--", "--print", "native-static-libs"])
+        .output()
+        .expect("Failed to run cargo");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "cargo failed:\n{}", stderr);
+
+    // rustc tells us which system libraries the static library needs.
+    let native_libs: Vec<&str> = stderr
+        .lines()
+        .find_map(|line| line.split("native-static-libs:").nth(1))
+        .map(|libs| libs.split_whitespace().collect())
+        .unwrap_or_default();
+
+    let exe = out_dir.join("capi_test");
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(root.join("tests/capi/test.c"))
+        .arg(out_dir.join("debug/librsdict.a"))
+        .args(&native_libs)
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("Failed to run the C compiler");
+    assert!(status.success(), "Failed to compile tests/capi/test.c");
+
+    let status = Command::new(&exe)
+        .status()
+        .expect("Failed to run the C test");
+    assert!(status.success(), "C test failed");
+}
@@ -0,0 +1,104 @@
+/* Exercises the C bindings.  `tests/capi.rs` builds and runs this. */
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "rsdict.h"
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,         \
+                    __LINE__, #cond);                                      \
+            exit(1);                                                       \
+        }                                                                  \
+    } while (0)
+
+static uint64_t xorshift(uint64_t x) {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    return x;
+}
+
+/* Check every query on `r` against the first `len` bits of `words`. */
+static void check_queries(const rsdict_t *r, const uint64_t *words, uint64_t len) {
+    uint64_t ones = 0, zeros = 0, pos;
+    CHECK(rsdict_len(r) == len);
+    for (uint64_t i = 0; i < len; i++) {
+        bool bit = (words[i / 64] >> (i % 64)) & 1;
+        CHECK(rsdict_get_bit(r, i) == bit);
+        CHECK(rsdict_rank(r, i, true) == ones);
+        CHECK(rsdict_rank(r, i, false) == zeros);
+        CHECK(rsdict_select(r, bit ? ones : zeros, bit, &pos));
+        CHECK(pos == i);
+        if (bit) {
+            ones++;
+        } else {
+            zeros++;
+        }
+    }
+    CHECK(rsdict_count_ones(r) == ones);
+    CHECK(!rsdict_get_bit(r, len));
+    CHECK(rsdict_rank(r, len, true) == ones);
+    CHECK(rsdict_rank(r, len + 100, false) == zeros);
+    pos = 12345;
+    CHECK(!rsdict_select(r, ones, true, &pos));
+    CHECK(!rsdict_select(r, zeros, false, &pos));
+    CHECK(pos == 12345);
+}
+
+int main(void) {
+    enum { NUM_WORDS = 100 };
+    uint64_t words[NUM_WORDS];
+    uint64_t x = 0x2545f4914f6cdd1d;
+    for (size_t i = 0; i < NUM_WORDS; i++) {
+        x = xorshift(x);
+        /* Mix in some sparse and empty words. */
+        words[i] = i % 3 == 0 ? x : i % 3 == 1 ? x & (x >> 7) & (x >> 13) : 0;
+    }
+    const uint64_t len = NUM_WORDS * 64 - 17;
+
+    rsdict_t *from_words = rsdict_from_words(words, NUM_WORDS, len);
+    CHECK(from_words != NULL);
+    check_queries(from_words, words, len);
+
+    rsdict_t *pushed = rsdict_new();
+    for (uint64_t i = 0; i < len; i++) {
+        rsdict_push(pushed, (words[i / 64] >> (i % 64)) & 1);
+    }
+    check_queries(pushed, words, len);
+
+    size_t size = rsdict_serialized_len(pushed);
+    CHECK(rsdict_serialize(pushed, NULL, 0) == size);
+    uint8_t *buf = malloc(size);
+    CHECK(buf != NULL);
+    CHECK(rsdict_serialize(pushed, buf, size) == size);
+    rsdict_t *loaded = rsdict_deserialize(buf, size);
+    CHECK(loaded != NULL);
+    check_queries(loaded, words, len);
+    CHECK(rsdict_fingerprint(loaded) == rsdict_fingerprint(from_words));
+    CHECK(rsdict_fingerprint(pushed) == rsdict_fingerprint(from_words));
+
+    CHECK(rsdict_deserialize(buf, size - 1) == NULL);
+    buf[0] ^= 0xff;
+    CHECK(rsdict_deserialize(buf, size) == NULL);
+    free(buf);
+
+    CHECK(rsdict_from_words(words, 1, 65) == NULL);
+#if SIZE_MAX < UINT64_MAX
+    /* Enough words for `len`, but too many bits to index. */
+    CHECK(rsdict_from_words(words, SIZE_MAX, (uint64_t)SIZE_MAX + 1) == NULL);
+#endif
+    rsdict_t *empty = rsdict_from_words(NULL, 0, 0);
+    CHECK(empty != NULL);
+    check_queries(empty, words, 0);
+    CHECK(rsdict_fingerprint(empty) != rsdict_fingerprint(from_words));
+
+    rsdict_free(from_words);
+    rsdict_free(pushed);
+    rsdict_free(loaded);
+    rsdict_free(empty);
+    rsdict_free(NULL);
+    return 0;
+}
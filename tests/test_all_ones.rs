@@ -1,3 +1,6 @@
+// `fid` only builds natively.
+#![cfg(not(target_arch = "wasm32"))]
+
 // other library used for correctness checks
 use fid::{FID, BitVector};
 
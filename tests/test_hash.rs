@@ -1,6 +1,3 @@
-// other library used for correctness checks
-extern crate fid;
-use fid::{FID, BitVector};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
@@ -38,4 +35,11 @@ fn test_hash() {
 
     assert_eq!(h1, h2);
 
+    // Trailing zeros are part of the bitmap.
+    r2.push(false);
+    let mut hasher = DefaultHasher::new();
+    r2.hash(&mut hasher);
+    assert_ne!(h1, hasher.finish());
+    assert_ne!(r1, r2);
+    assert!(r1 < r2);
 }
\ No newline at end of file